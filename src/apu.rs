@@ -0,0 +1,695 @@
+use crate::audio::{AudioSink, Resampler};
+
+const WAVE_RAM_SIZE: usize = 0x10;
+// the frame sequencer is clocked by the falling edge of bit 4 of div, which happens at 512 Hz
+const FRAME_SEQUENCER_DIVIDER_BIT: u16 = 1 << 12;
+
+const DUTY_PATTERNS: [[u8; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1], // 12.5%
+    [1, 0, 0, 0, 0, 0, 0, 1], // 25%
+    [1, 0, 0, 0, 0, 1, 1, 1], // 50%
+    [0, 1, 1, 1, 1, 1, 1, 0], // 75%
+];
+
+const NOISE_DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+struct LengthCounter {
+    enabled: bool,
+    counter: u16,
+    max: u16,
+}
+
+impl LengthCounter {
+    fn new(max: u16) -> LengthCounter {
+        LengthCounter {
+            enabled: false,
+            counter: 0,
+            max,
+        }
+    }
+
+    fn load(&mut self, length: u16) {
+        self.counter = self.max - length;
+    }
+
+    fn trigger(&mut self) {
+        if self.counter == 0 {
+            self.counter = self.max;
+        }
+    }
+
+    /// Returns true when the counter runs out and the channel should be silenced.
+    fn clock(&mut self) -> bool {
+        if self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            return self.counter == 0;
+        }
+        false
+    }
+}
+
+struct Envelope {
+    initial_volume: u8,
+    increasing: bool,
+    period: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    fn new() -> Envelope {
+        Envelope {
+            initial_volume: 0,
+            increasing: false,
+            period: 0,
+            volume: 0,
+            timer: 0,
+        }
+    }
+
+    fn read_byte(&self) -> u8 {
+        self.initial_volume << 4 | (self.increasing as u8) << 3 | self.period
+    }
+
+    fn write_byte(&mut self, value: u8) {
+        self.initial_volume = value >> 4;
+        self.increasing = value & 0b1000 != 0;
+        self.period = value & 0b111;
+    }
+
+    /// The DAC is powered whenever the upper five bits of the envelope register are set.
+    fn is_dac_enabled(&self) -> bool {
+        self.read_byte() & 0xF8 != 0
+    }
+
+    fn trigger(&mut self) {
+        self.volume = self.initial_volume;
+        self.timer = self.period;
+    }
+
+    fn clock(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.increasing && self.volume < 15 {
+                self.volume += 1;
+            } else if !self.increasing && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+}
+
+struct Sweep {
+    period: u8,
+    negate: bool,
+    shift: u8,
+    timer: u8,
+    enabled: bool,
+    shadow_frequency: u16,
+}
+
+impl Sweep {
+    fn new() -> Sweep {
+        Sweep {
+            period: 0,
+            negate: false,
+            shift: 0,
+            timer: 0,
+            enabled: false,
+            shadow_frequency: 0,
+        }
+    }
+
+    fn read_byte(&self) -> u8 {
+        0x80 | self.period << 4 | (self.negate as u8) << 3 | self.shift
+    }
+
+    fn write_byte(&mut self, value: u8) {
+        self.period = (value >> 4) & 0b111;
+        self.negate = value & 0b1000 != 0;
+        self.shift = value & 0b111;
+    }
+
+    fn reload_timer(&mut self) {
+        // a period of 0 is treated as 8
+        self.timer = if self.period == 0 { 8 } else { self.period };
+    }
+
+    fn next_frequency(&self) -> u16 {
+        let delta = self.shadow_frequency >> self.shift;
+        if self.negate {
+            self.shadow_frequency.wrapping_sub(delta)
+        } else {
+            self.shadow_frequency + delta
+        }
+    }
+}
+
+struct SquareChannel {
+    enabled: bool,
+    duty: u8,
+    duty_step: usize,
+    frequency: u16,
+    timer: u32,
+    length: LengthCounter,
+    envelope: Envelope,
+    // only channel 1 has a frequency sweep unit
+    sweep: Option<Sweep>,
+}
+
+impl SquareChannel {
+    fn new(sweep: Option<Sweep>) -> SquareChannel {
+        SquareChannel {
+            enabled: false,
+            duty: 0,
+            duty_step: 0,
+            frequency: 0,
+            timer: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            sweep,
+        }
+    }
+
+    fn read_byte(&self, register: u16) -> u8 {
+        match register {
+            0 => self.sweep.as_ref().map_or(0xFF, |sweep| sweep.read_byte()),
+            1 => self.duty << 6 | 0x3F,
+            2 => self.envelope.read_byte(),
+            3 => 0xFF,
+            4 => 0xBF | (self.length.enabled as u8) << 6,
+            _ => unreachable!()
+        }
+    }
+
+    fn write_byte(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                if let Some(sweep) = self.sweep.as_mut() {
+                    sweep.write_byte(value);
+                }
+            }
+            1 => {
+                self.duty = value >> 6;
+                self.length.load((value & 0x3F) as u16);
+            }
+            2 => {
+                self.envelope.write_byte(value);
+                if !self.envelope.is_dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => self.frequency = (self.frequency & 0x700) | value as u16,
+            4 => {
+                self.frequency = (self.frequency & 0xFF) | ((value as u16 & 0b111) << 8);
+                self.length.enabled = value & 0x40 != 0;
+                if value & 0x80 != 0 {
+                    self.trigger();
+                }
+            }
+            _ => unreachable!()
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.is_dac_enabled();
+        self.length.trigger();
+        self.timer = self.period();
+        self.envelope.trigger();
+
+        let frequency = self.frequency;
+        let mut overflow = false;
+        if let Some(sweep) = self.sweep.as_mut() {
+            sweep.shadow_frequency = frequency;
+            sweep.reload_timer();
+            sweep.enabled = sweep.period != 0 || sweep.shift != 0;
+            overflow = sweep.shift != 0 && sweep.next_frequency() > 0x7FF;
+        }
+        if overflow {
+            self.enabled = false;
+        }
+    }
+
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 4
+    }
+
+    fn tick(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer == 0 {
+            self.timer = self.period();
+            self.duty_step = (self.duty_step + 1) % 8;
+        }
+    }
+
+    fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    fn clock_sweep(&mut self) {
+        let sweep = match self.sweep.as_mut() {
+            Some(sweep) => sweep,
+            None => return,
+        };
+        if sweep.timer > 0 {
+            sweep.timer -= 1;
+        }
+        if sweep.timer != 0 {
+            return;
+        }
+        sweep.reload_timer();
+        if !sweep.enabled || sweep.period == 0 {
+            return;
+        }
+
+        let frequency = sweep.next_frequency();
+        if frequency > 0x7FF {
+            self.enabled = false;
+        } else if sweep.shift != 0 {
+            sweep.shadow_frequency = frequency;
+            self.frequency = frequency;
+            // the new frequency is checked for overflow again straight away
+            if sweep.next_frequency() > 0x7FF {
+                self.enabled = false;
+            }
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+        DUTY_PATTERNS[self.duty as usize][self.duty_step] * self.envelope.volume
+    }
+}
+
+struct WaveChannel {
+    enabled: bool,
+    dac_enabled: bool,
+    volume_code: u8,
+    frequency: u16,
+    timer: u32,
+    position: usize,
+    length: LengthCounter,
+    wave_ram: [u8; WAVE_RAM_SIZE],
+}
+
+impl WaveChannel {
+    fn new() -> WaveChannel {
+        WaveChannel {
+            enabled: false,
+            dac_enabled: false,
+            volume_code: 0,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            length: LengthCounter::new(256),
+            wave_ram: [0; WAVE_RAM_SIZE],
+        }
+    }
+
+    fn read_byte(&self, register: u16) -> u8 {
+        match register {
+            0 => 0x7F | (self.dac_enabled as u8) << 7,
+            1 => 0xFF,
+            2 => 0x9F | self.volume_code << 5,
+            3 => 0xFF,
+            4 => 0xBF | (self.length.enabled as u8) << 6,
+            _ => unreachable!()
+        }
+    }
+
+    fn write_byte(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.dac_enabled = value & 0x80 != 0;
+                if !self.dac_enabled {
+                    self.enabled = false;
+                }
+            }
+            1 => self.length.load(value as u16),
+            2 => self.volume_code = (value >> 5) & 0b11,
+            3 => self.frequency = (self.frequency & 0x700) | value as u16,
+            4 => {
+                self.frequency = (self.frequency & 0xFF) | ((value as u16 & 0b111) << 8);
+                self.length.enabled = value & 0x40 != 0;
+                if value & 0x80 != 0 {
+                    self.trigger();
+                }
+            }
+            _ => unreachable!()
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled;
+        self.length.trigger();
+        self.timer = self.period();
+        self.position = 0;
+    }
+
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 2
+    }
+
+    fn tick(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer == 0 {
+            self.timer = self.period();
+            self.position = (self.position + 1) % (WAVE_RAM_SIZE * 2);
+        }
+    }
+
+    fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+        // each byte holds two samples, high nibble first
+        let byte = self.wave_ram[self.position / 2];
+        let sample = if self.position & 1 == 0 { byte >> 4 } else { byte & 0x0F };
+        match self.volume_code {
+            0 => 0,
+            1 => sample,
+            2 => sample >> 1,
+            3 => sample >> 2,
+            _ => unreachable!()
+        }
+    }
+}
+
+struct NoiseChannel {
+    enabled: bool,
+    clock_shift: u8,
+    short_mode: bool,
+    divisor_code: u8,
+    timer: u32,
+    lfsr: u16,
+    length: LengthCounter,
+    envelope: Envelope,
+}
+
+impl NoiseChannel {
+    fn new() -> NoiseChannel {
+        NoiseChannel {
+            enabled: false,
+            clock_shift: 0,
+            short_mode: false,
+            divisor_code: 0,
+            timer: 0,
+            lfsr: 0x7FFF,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+        }
+    }
+
+    fn read_byte(&self, register: u16) -> u8 {
+        match register {
+            0 => 0xFF,
+            1 => 0xFF,
+            2 => self.envelope.read_byte(),
+            3 => self.clock_shift << 4 | (self.short_mode as u8) << 3 | self.divisor_code,
+            4 => 0xBF | (self.length.enabled as u8) << 6,
+            _ => unreachable!()
+        }
+    }
+
+    fn write_byte(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {}
+            1 => self.length.load((value & 0x3F) as u16),
+            2 => {
+                self.envelope.write_byte(value);
+                if !self.envelope.is_dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => {
+                self.clock_shift = value >> 4;
+                self.short_mode = value & 0b1000 != 0;
+                self.divisor_code = value & 0b111;
+            }
+            4 => {
+                self.length.enabled = value & 0x40 != 0;
+                if value & 0x80 != 0 {
+                    self.trigger();
+                }
+            }
+            _ => unreachable!()
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.is_dac_enabled();
+        self.length.trigger();
+        self.timer = self.period();
+        self.envelope.trigger();
+        self.lfsr = 0x7FFF;
+    }
+
+    fn period(&self) -> u32 {
+        NOISE_DIVISORS[self.divisor_code as usize] << self.clock_shift
+    }
+
+    fn tick(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer == 0 {
+            self.timer = self.period();
+            let feedback = (self.lfsr & 1) ^ ((self.lfsr >> 1) & 1);
+            self.lfsr = (self.lfsr >> 1) | (feedback << 14);
+            if self.short_mode {
+                self.lfsr = (self.lfsr & !(1 << 6)) | (feedback << 6);
+            }
+        }
+    }
+
+    fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+        // the output is the inverted lowest bit of the shift register
+        (!self.lfsr & 1) as u8 * self.envelope.volume
+    }
+}
+
+pub struct APU {
+    square_one: SquareChannel,
+    square_two: SquareChannel,
+    wave: WaveChannel,
+    noise: NoiseChannel,
+    master_volume: u8, // nr50
+    panning: u8, // nr51
+    powered: bool, // bit 7 of nr52
+    divider_bit: bool,
+    // the divider runs twice as fast in CGB double speed, so a higher bit is watched
+    double_speed: bool,
+    frame_sequencer_step: u8,
+    sink: Option<(Box<dyn AudioSink>, Resampler)>,
+}
+
+impl APU {
+    pub fn new() -> APU {
+        APU {
+            square_one: SquareChannel::new(Some(Sweep::new())),
+            square_two: SquareChannel::new(None),
+            wave: WaveChannel::new(),
+            noise: NoiseChannel::new(),
+            master_volume: 0x77,
+            panning: 0xF3,
+            powered: true,
+            divider_bit: false,
+            double_speed: false,
+            frame_sequencer_step: 0,
+            sink: None,
+        }
+    }
+
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0xFF10 ..= 0xFF14 => self.square_one.read_byte(address - 0xFF10),
+            0xFF15 ..= 0xFF19 => self.square_two.read_byte(address - 0xFF15),
+            0xFF1A ..= 0xFF1E => self.wave.read_byte(address - 0xFF1A),
+            0xFF1F ..= 0xFF23 => self.noise.read_byte(address - 0xFF1F),
+            0xFF24 => self.master_volume,
+            0xFF25 => self.panning,
+            0xFF26 => {
+                0x70 | (self.powered as u8) << 7
+                    | (self.noise.enabled as u8) << 3
+                    | (self.wave.enabled as u8) << 2
+                    | (self.square_two.enabled as u8) << 1
+                    | self.square_one.enabled as u8
+            }
+            0xFF27 ..= 0xFF2F => 0xFF,
+            0xFF30 ..= 0xFF3F => self.wave.wave_ram[address as usize - 0xFF30],
+            _ => unreachable!("Invalid address accessed in apu: {}", address)
+        }
+    }
+
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        // while powered off only nr52 and wave ram can be written
+        if !self.powered && address < 0xFF26 {
+            return;
+        }
+        match address {
+            0xFF10 ..= 0xFF14 => self.square_one.write_byte(address - 0xFF10, value),
+            0xFF15 ..= 0xFF19 => self.square_two.write_byte(address - 0xFF15, value),
+            0xFF1A ..= 0xFF1E => self.wave.write_byte(address - 0xFF1A, value),
+            0xFF1F ..= 0xFF23 => self.noise.write_byte(address - 0xFF1F, value),
+            0xFF24 => self.master_volume = value,
+            0xFF25 => self.panning = value,
+            0xFF26 => {
+                let powered = value & 0x80 != 0;
+                if self.powered && !powered {
+                    self.power_off();
+                } else if !self.powered && powered {
+                    self.frame_sequencer_step = 0;
+                }
+                self.powered = powered;
+            }
+            0xFF27 ..= 0xFF2F => {}
+            0xFF30 ..= 0xFF3F => self.wave.wave_ram[address as usize - 0xFF30] = value,
+            _ => unreachable!("Invalid address accessed in apu: {}", address)
+        }
+    }
+
+    /// Powering off clears every register, but leaves wave ram alone.
+    fn power_off(&mut self) {
+        let wave_ram = self.wave.wave_ram;
+        self.square_one = SquareChannel::new(Some(Sweep::new()));
+        self.square_two = SquareChannel::new(None);
+        self.wave = WaveChannel::new();
+        self.wave.wave_ram = wave_ram;
+        self.noise = NoiseChannel::new();
+        self.master_volume = 0;
+        self.panning = 0;
+    }
+
+    pub fn set_audio_sink(&mut self, sink: Box<dyn AudioSink>) {
+        let resampler = Resampler::new(sink.sample_rate());
+        self.sink = Some((sink, resampler));
+    }
+
+    pub fn audio_error(&self) -> Option<&std::io::Error> {
+        self.sink.as_ref().and_then(|(sink, _)| sink.error())
+    }
+
+    pub fn tick(&mut self, elapsed: u32) {
+        for _ in 0..elapsed {
+            if self.powered {
+                // a machine cycle is four dots
+                for _ in 0..4 {
+                    self.square_one.tick();
+                    self.square_two.tick();
+                    self.wave.tick();
+                    self.noise.tick();
+                }
+            }
+
+            if let Some((mut sink, mut resampler)) = self.sink.take() {
+                let (left, right) = self.sample();
+                if let Some((left, right)) = resampler.push(left, right) {
+                    sink.write_sample(left, right);
+                }
+                self.sink = Some((sink, resampler));
+            }
+        }
+    }
+
+    /// Observes the timer's internal divider, clocking the frame sequencer on the falling
+    /// edge of its bit. This has to be called after div is reset as well, since that can
+    /// produce an extra edge.
+    pub fn update_divider(&mut self, divider: u16) {
+        let mask = if self.double_speed { FRAME_SEQUENCER_DIVIDER_BIT << 1 } else { FRAME_SEQUENCER_DIVIDER_BIT };
+        let bit = divider & mask != 0;
+        if self.divider_bit && !bit && self.powered {
+            self.clock_frame_sequencer();
+        }
+        self.divider_bit = bit;
+    }
+
+    pub fn set_double_speed(&mut self, double_speed: bool) {
+        self.double_speed = double_speed;
+    }
+
+    fn clock_frame_sequencer(&mut self) {
+        // length runs on even steps, sweep on steps 2 and 6, and envelope on step 7
+        if self.frame_sequencer_step & 1 == 0 {
+            self.square_one.clock_length();
+            self.square_two.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+        if self.frame_sequencer_step == 2 || self.frame_sequencer_step == 6 {
+            self.square_one.clock_sweep();
+        }
+        if self.frame_sequencer_step == 7 {
+            self.square_one.envelope.clock();
+            self.square_two.envelope.clock();
+            self.noise.envelope.clock();
+        }
+        self.frame_sequencer_step = (self.frame_sequencer_step + 1) % 8;
+    }
+
+    /// The current mixed output as a (left, right) pair, each between -1.0 and 1.0.
+    pub fn sample(&self) -> (f32, f32) {
+        if !self.powered {
+            return (0.0, 0.0);
+        }
+        let channels = [
+            Self::dac_output(self.square_one.envelope.is_dac_enabled(), self.square_one.output()),
+            Self::dac_output(self.square_two.envelope.is_dac_enabled(), self.square_two.output()),
+            Self::dac_output(self.wave.dac_enabled, self.wave.output()),
+            Self::dac_output(self.noise.envelope.is_dac_enabled(), self.noise.output()),
+        ];
+
+        let mut left = 0.0;
+        let mut right = 0.0;
+        for (i, output) in channels.iter().enumerate() {
+            // nr51 has the right enables in the low nibble and the left ones in the high nibble
+            if self.panning & (1 << i) != 0 {
+                right += output;
+            }
+            if self.panning & (1 << (i + 4)) != 0 {
+                left += output;
+            }
+        }
+
+        let left_volume = (((self.master_volume >> 4) & 0b111) + 1) as f32 / 8.0;
+        let right_volume = ((self.master_volume & 0b111) + 1) as f32 / 8.0;
+        (left / 4.0 * left_volume, right / 4.0 * right_volume)
+    }
+
+    /// Converts a channel's 4-bit digital output to an analog level.
+    fn dac_output(enabled: bool, value: u8) -> f32 {
+        if enabled {
+            value as f32 / 7.5 - 1.0
+        } else {
+            0.0
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+// the APU produces a new output level every machine cycle
+const APU_SAMPLE_RATE: u32 = 4_194_304 / 4;
+const WAV_HEADER_SIZE: u32 = 44;
+
+/// Receives the APU's mixed output, already resampled to the sink's sample rate.
+pub trait AudioSink {
+    fn sample_rate(&self) -> u32;
+    fn write_sample(&mut self, left: f32, right: f32);
+    /// The error that stopped the sink, if any. Samples are written from inside `CPU::tick`,
+    /// so sinks that can fail keep their error for the frontend to check instead.
+    fn error(&self) -> Option<&std::io::Error> { None }
+}
+
+/// Downsamples the APU output by averaging every sample that falls in each output period.
+pub struct Resampler {
+    output_rate: u32,
+    phase: u32,
+    left: f32,
+    right: f32,
+    count: u32,
+}
+
+impl Resampler {
+    pub fn new(output_rate: u32) -> Resampler {
+        Resampler {
+            output_rate,
+            phase: 0,
+            left: 0.0,
+            right: 0.0,
+            count: 0,
+        }
+    }
+
+    /// Takes one sample at the APU rate, returning an output sample once enough input has
+    /// been accumulated.
+    pub fn push(&mut self, left: f32, right: f32) -> Option<(f32, f32)> {
+        self.left += left;
+        self.right += right;
+        self.count += 1;
+
+        self.phase += self.output_rate;
+        if self.phase < APU_SAMPLE_RATE {
+            return None;
+        }
+        self.phase -= APU_SAMPLE_RATE;
+
+        let sample = (self.left / self.count as f32, self.right / self.count as f32);
+        self.left = 0.0;
+        self.right = 0.0;
+        self.count = 0;
+        Some(sample)
+    }
+}
+
+/// Writes 16-bit stereo PCM to a WAV file. The header is rewritten every second of audio, so
+/// the file stays playable even if the process is killed mid-session. The first I/O error
+/// stops the recording.
+pub struct WavWriter {
+    writer: BufWriter<File>,
+    sample_rate: u32,
+    samples_written: u32,
+    error: Option<std::io::Error>,
+}
+
+impl WavWriter {
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32) -> std::io::Result<WavWriter> {
+        let mut wav_writer = WavWriter {
+            writer: BufWriter::new(File::create(path)?),
+            sample_rate,
+            samples_written: 0,
+            error: None,
+        };
+        wav_writer.write_header()?;
+        Ok(wav_writer)
+    }
+
+    fn write_header(&mut self) -> std::io::Result<()> {
+        let data_size = self.samples_written * 4;
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(b"RIFF")?;
+        self.writer.write_all(&(WAV_HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+        self.writer.write_all(b"WAVEfmt ")?;
+        self.writer.write_all(&16u32.to_le_bytes())?; // fmt chunk size
+        self.writer.write_all(&1u16.to_le_bytes())?; // pcm
+        self.writer.write_all(&2u16.to_le_bytes())?; // channels
+        self.writer.write_all(&self.sample_rate.to_le_bytes())?;
+        self.writer.write_all(&(self.sample_rate * 4).to_le_bytes())?; // byte rate
+        self.writer.write_all(&4u16.to_le_bytes())?; // block align
+        self.writer.write_all(&16u16.to_le_bytes())?; // bits per sample
+        self.writer.write_all(b"data")?;
+        self.writer.write_all(&data_size.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()
+    }
+
+    fn write_pcm(&mut self, left: f32, right: f32) -> std::io::Result<()> {
+        self.writer.write_all(&Self::to_pcm(left))?;
+        self.writer.write_all(&Self::to_pcm(right))?;
+        self.samples_written += 1;
+        if self.samples_written.is_multiple_of(self.sample_rate) {
+            self.write_header()?;
+        }
+        Ok(())
+    }
+
+    fn to_pcm(value: f32) -> [u8; 2] {
+        ((value.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes()
+    }
+}
+
+impl AudioSink for WavWriter {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn write_sample(&mut self, left: f32, right: f32) {
+        if self.error.is_some() {
+            return;
+        }
+        if let Err(error) = self.write_pcm(left, right) {
+            self.error = Some(error);
+        }
+    }
+
+    fn error(&self) -> Option<&std::io::Error> {
+        self.error.as_ref()
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        if self.error.is_some() {
+            return;
+        }
+        let _ = self.write_header();
+    }
+}
@@ -0,0 +1,260 @@
+use std::fmt::{Display, Formatter};
+
+const HEADER_END: usize = 0x150;
+
+/// Metadata parsed from the cartridge header at 0x0100-0x014F.
+pub struct Cartridge {
+    pub title: String,
+    pub manufacturer_code: String,
+    pub cgb_flag: u8,
+    pub new_licensee_code: String,
+    pub sgb_flag: u8,
+    pub cartridge_type: u8,
+    pub rom_size_code: u8,
+    pub ram_size_code: u8,
+    pub destination_code: u8,
+    pub old_licensee_code: u8,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+    computed_header_checksum: u8,
+    computed_global_checksum: u16,
+}
+
+impl Cartridge {
+    /// Returns None if the rom is too small to hold a header.
+    pub fn parse(rom: &[u8]) -> Option<Cartridge> {
+        if rom.len() < HEADER_END {
+            return None;
+        }
+
+        let cgb_flag = rom[0x143];
+        // CGB-era carts shortened the title to make room for the manufacturer code and CGB flag
+        let (title, manufacturer_code) = if cgb_flag & 0x80 != 0 {
+            (Self::read_string(&rom[0x134..0x13F]), Self::read_string(&rom[0x13F..0x143]))
+        } else {
+            (Self::read_string(&rom[0x134..0x144]), String::new())
+        };
+
+        let computed_header_checksum = rom[0x134..=0x14C].iter()
+            .fold(0u8, |checksum, byte| checksum.wrapping_sub(*byte).wrapping_sub(1));
+        // the global checksum covers the whole rom except for itself
+        let computed_global_checksum = rom.iter().enumerate()
+            .filter(|(i, _)| *i != 0x14E && *i != 0x14F)
+            .fold(0u16, |checksum, (_, byte)| checksum.wrapping_add(*byte as u16));
+
+        Some(Cartridge {
+            title,
+            manufacturer_code,
+            cgb_flag,
+            new_licensee_code: Self::read_string(&rom[0x144..0x146]),
+            sgb_flag: rom[0x146],
+            cartridge_type: rom[0x147],
+            rom_size_code: rom[0x148],
+            ram_size_code: rom[0x149],
+            destination_code: rom[0x14A],
+            old_licensee_code: rom[0x14B],
+            version: rom[0x14C],
+            header_checksum: rom[0x14D],
+            global_checksum: (rom[0x14E] as u16) << 8 | rom[0x14F] as u16,
+            computed_header_checksum,
+            computed_global_checksum,
+        })
+    }
+
+    fn read_string(bytes: &[u8]) -> String {
+        bytes.iter()
+            .take_while(|byte| **byte != 0)
+            .map(|byte| if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '?' })
+            .collect::<String>()
+            .trim_end()
+            .to_string()
+    }
+
+    /// The boot rom refuses to start carts with a bad header checksum.
+    pub fn is_header_checksum_valid(&self) -> bool {
+        self.header_checksum == self.computed_header_checksum
+    }
+
+    /// Real hardware never checks this one, so plenty of carts get it wrong.
+    pub fn is_global_checksum_valid(&self) -> bool {
+        self.global_checksum == self.computed_global_checksum
+    }
+
+    pub fn supports_cgb(&self) -> bool {
+        self.cgb_flag & 0x80 != 0
+    }
+
+    pub fn requires_cgb(&self) -> bool {
+        self.cgb_flag == 0xC0
+    }
+
+    pub fn supports_sgb(&self) -> bool {
+        self.sgb_flag == 0x03
+    }
+
+    pub fn is_japanese(&self) -> bool {
+        self.destination_code == 0x00
+    }
+
+    /// The licensee, as either the two character new code or the hex old one.
+    pub fn licensee_code(&self) -> String {
+        // 0x33 means the new licensee code is used instead
+        if self.old_licensee_code == 0x33 {
+            self.new_licensee_code.clone()
+        } else {
+            format!("{:02X}", self.old_licensee_code)
+        }
+    }
+
+    /// The declared rom size in bytes, or None for an unknown size code.
+    pub fn rom_size(&self) -> Option<usize> {
+        match self.rom_size_code {
+            0x00 ..= 0x08 => Some(0x8000 << self.rom_size_code),
+            _ => None
+        }
+    }
+
+    /// The declared external ram size in bytes, or None for an unknown size code.
+    pub fn ram_size(&self) -> Option<usize> {
+        match self.ram_size_code {
+            0x00 => Some(0),
+            // only ever used by homebrew, but documented as a single 2 KB bank
+            0x01 => Some(0x800),
+            0x02 => Some(0x2000),
+            0x03 => Some(0x8000),
+            0x04 => Some(0x20000),
+            0x05 => Some(0x10000),
+            _ => None
+        }
+    }
+
+    /// Whether the cartridge has a battery keeping its ram alive.
+    pub fn has_battery(&self) -> bool {
+        matches!(self.cartridge_type, 0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFF)
+    }
+
+    pub fn cartridge_type_name(&self) -> &'static str {
+        match self.cartridge_type {
+            0x00 => "ROM ONLY",
+            0x01 => "MBC1",
+            0x02 => "MBC1+RAM",
+            0x03 => "MBC1+RAM+BATTERY",
+            0x05 => "MBC2",
+            0x06 => "MBC2+BATTERY",
+            0x08 => "ROM+RAM",
+            0x09 => "ROM+RAM+BATTERY",
+            0x0B => "MMM01",
+            0x0C => "MMM01+RAM",
+            0x0D => "MMM01+RAM+BATTERY",
+            0x0F => "MBC3+TIMER+BATTERY",
+            0x10 => "MBC3+TIMER+RAM+BATTERY",
+            0x11 => "MBC3",
+            0x12 => "MBC3+RAM",
+            0x13 => "MBC3+RAM+BATTERY",
+            0x19 => "MBC5",
+            0x1A => "MBC5+RAM",
+            0x1B => "MBC5+RAM+BATTERY",
+            0x1C => "MBC5+RUMBLE",
+            0x1D => "MBC5+RUMBLE+RAM",
+            0x1E => "MBC5+RUMBLE+RAM+BATTERY",
+            0x20 => "MBC6",
+            0x22 => "MBC7+SENSOR+RUMBLE+RAM+BATTERY",
+            0xFC => "POCKET CAMERA",
+            0xFD => "BANDAI TAMA5",
+            0xFE => "HuC3",
+            0xFF => "HuC1+RAM+BATTERY",
+            _ => "UNKNOWN"
+        }
+    }
+}
+
+impl Display for Cartridge {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let size = |size: Option<usize>| size.map_or(String::from("unknown"), |size| format!("{} KB", size / 1024));
+        let validity = |valid: bool| if valid { "ok" } else { "MISMATCH" };
+
+        writeln!(f, "Title:             {}", self.title)?;
+        writeln!(f, "Manufacturer code: {}", self.manufacturer_code)?;
+        writeln!(f, "CGB flag:          {:02X}{}", self.cgb_flag,
+                 if self.requires_cgb() { " (CGB only)" } else if self.supports_cgb() { " (CGB enhanced)" } else { "" })?;
+        writeln!(f, "SGB flag:          {:02X}{}", self.sgb_flag, if self.supports_sgb() { " (SGB enhanced)" } else { "" })?;
+        writeln!(f, "Cartridge type:    {:02X} ({})", self.cartridge_type, self.cartridge_type_name())?;
+        writeln!(f, "ROM size:          {:02X} ({})", self.rom_size_code, size(self.rom_size()))?;
+        writeln!(f, "RAM size:          {:02X} ({})", self.ram_size_code, size(self.ram_size()))?;
+        writeln!(f, "Destination:       {:02X} ({})", self.destination_code,
+                 if self.is_japanese() { "Japan" } else { "Overseas" })?;
+        writeln!(f, "Licensee:          {}", self.licensee_code())?;
+        writeln!(f, "Version:           {:02X}", self.version)?;
+        writeln!(f, "Header checksum:   {:02X} ({})", self.header_checksum, validity(self.is_header_checksum_valid()))?;
+        write!(f, "Global checksum:   {:04X} ({})", self.global_checksum, validity(self.is_global_checksum_valid()))
+    }
+}
+
+/// A synthetic rom for tests across the crate, made of `banks` 16KiB banks that each start
+/// with their own bank number. The entry point jumps to 0x0150.
+#[cfg(test)]
+pub(crate) fn test_rom(cartridge_type: u8, banks: usize) -> Vec<u8> {
+    let mut rom = vec![0; banks * 0x4000];
+    for bank in 0..banks {
+        rom[bank * 0x4000] = bank as u8;
+    }
+    // nop, jp $0150
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x147] = cartridge_type;
+    rom[0x148] = (banks / 2).trailing_zeros() as u8;
+    rom
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Just a header, titled TEST, with both checksums worked out by hand.
+    fn header() -> Vec<u8> {
+        let mut rom = vec![0; HEADER_END];
+        rom[0x134..0x138].copy_from_slice(b"TEST");
+        rom[0x14D] = 0xA7;
+        rom[0x14E] = 0x01;
+        rom[0x14F] = 0xE7;
+        rom
+    }
+
+    #[test]
+    fn parses_valid_checksums() {
+        let cartridge = Cartridge::parse(&header()).unwrap();
+        assert_eq!(cartridge.title, "TEST");
+        assert!(cartridge.is_header_checksum_valid());
+        assert!(cartridge.is_global_checksum_valid());
+    }
+
+    #[test]
+    fn header_checksum_covers_the_title_through_the_version() {
+        let mut rom = header();
+        rom[0x14C] = 1;
+        let cartridge = Cartridge::parse(&rom).unwrap();
+        assert!(!cartridge.is_header_checksum_valid());
+        assert!(!cartridge.is_global_checksum_valid());
+
+        // outside the header checksum, but still part of the global one
+        let mut rom = header();
+        rom[0x100] = 1;
+        let cartridge = Cartridge::parse(&rom).unwrap();
+        assert!(cartridge.is_header_checksum_valid());
+        assert!(!cartridge.is_global_checksum_valid());
+    }
+
+    #[test]
+    fn global_checksum_skips_its_own_bytes() {
+        // the sum doesn't change along with the stored value, as it would if it included it
+        let mut rom = header();
+        rom.push(0x01);
+        rom[0x14F] = 0xE8;
+        assert!(Cartridge::parse(&rom).unwrap().is_global_checksum_valid());
+    }
+
+    #[test]
+    fn rejects_roms_without_a_header() {
+        assert!(Cartridge::parse(&[0; HEADER_END - 1]).is_none());
+    }
+}
@@ -2,35 +2,144 @@ use Option::{Some, None};
 use crate::register::{Registers, CPUFlag};
 use crate::opcode::Opcode;
 use crate::mmu::MMU;
+use crate::ppu::{SCREEN_WIDTH, SCREEN_HEIGHT};
+use crate::joypad::Button;
+use crate::audio::AudioSink;
+use crate::serial::SerialPeer;
+use crate::cartridge::Cartridge;
+use crate::error::{LoadError, EmulationError, EmulationErrorKind};
+
+const SPEED_SWITCH_CYCLES: u32 = 2050;
 
 pub struct CPU {
     registers: Registers,
     pub mmu: MMU,
     ime: bool,
     ime_timer: u8,
-    low_power_mode: bool
+    low_power_mode: bool,
+    // after STOP the whole system clock halts until a button is pressed
+    stopped: bool,
+    // the next fetch doesn't advance pc, see Opcode::HALT
+    halt_bug: bool,
+    // machine cycles already ticked by memory accesses during the current step
+    bus_cycles: u32,
+    // machine cycles run since power on
+    cycles: u64,
+    // set by an illegal opcode, which hangs the CPU until reset
+    locked_up: Option<EmulationError>
 }
 
 impl CPU {
-    pub fn new(rom: &[u8]) -> CPU {
-        Self {
+    pub fn new(rom: &[u8]) -> Result<CPU, LoadError> {
+        Ok(Self {
             registers: Registers::new(),
-            mmu: MMU::new(rom),
+            mmu: MMU::new(rom)?,
             ime: false,
             ime_timer: 0,
-            low_power_mode: false
-        }
+            low_power_mode: false,
+            stopped: false,
+            halt_bug: false,
+            bus_cycles: 0,
+            cycles: 0,
+            locked_up: None
+        })
+    }
+
+    /// Starts from a boot rom instead of the state it leaves behind, so the logo scrolls
+    /// in before the cartridge takes over.
+    pub fn with_boot_rom(rom: &[u8], boot_rom: &[u8]) -> Result<CPU, LoadError> {
+        let mut cpu = Self::new(rom)?;
+        cpu.mmu.load_boot_rom(boot_rom)?;
+        cpu.registers = Registers::zeroed();
+        Ok(cpu)
     }
 
-    pub fn tick(&mut self) {
+    /// Runs a single instruction. On error the CPU is left pointing past the faulting
+    /// opcode, so the emulator should not be ticked any further. While stopped no time
+    /// passes at all until a button is pressed.
+    pub fn tick(&mut self) -> Result<(), EmulationError> {
+        if self.stopped {
+            if !self.mmu.is_joypad_line_low() {
+                return Ok(());
+            }
+            self.stopped = false;
+        }
         self.update_timers();
-        self.handle_interrupt();
-        let elapsed = if self.low_power_mode {
+        self.bus_cycles = 0;
+        let mut elapsed = self.handle_interrupt();
+        // a locked up CPU stops fetching, but the rest of the hardware keeps running
+        elapsed += if self.low_power_mode || self.locked_up.is_some() {
             1
         } else {
-            self.execute()
+            self.execute()?
         };
-        self.mmu.tick(elapsed);
+        // memory accesses already advanced the hardware, the rest are internal cycles
+        self.mmu.tick(elapsed.saturating_sub(self.bus_cycles));
+        self.cycles += elapsed as u64;
+        Ok(())
+    }
+
+    /// Machine cycles run since power on, not counting the time spent stopped.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// The illegal opcode that hung the CPU, if any. Like on hardware nothing but a reset
+    /// recovers from this, so the frontend should report it.
+    pub fn lock_up(&self) -> Option<&EmulationError> {
+        self.locked_up.as_ref()
+    }
+
+    pub fn press(&mut self, button: Button) {
+        self.mmu.press(button);
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.mmu.release(button);
+    }
+
+    pub fn set_serial_peer(&mut self, peer: Box<dyn SerialPeer>) {
+        self.mmu.set_serial_peer(peer);
+    }
+
+    /// Drains the bytes sent over the serial port since the last call. Call it regularly, as
+    /// only the latest 64KiB are kept in between.
+    pub fn take_serial_output(&mut self) -> Vec<u8> {
+        self.mmu.take_serial_output()
+    }
+
+    /// Drains the rumble motor state changes since the last call, true meaning it turned on.
+    /// Only rumble cartridges ever produce events, and only the latest few hundred are kept
+    /// in between calls.
+    pub fn take_rumble_events(&mut self) -> Vec<bool> {
+        self.mmu.take_rumble_events()
+    }
+
+    pub fn cartridge(&self) -> &Cartridge {
+        self.mmu.cartridge()
+    }
+
+    /// The cartridge ram, plus any clock state, in the layout used by .sav files.
+    pub fn export_save(&self) -> Vec<u8> {
+        self.mmu.export_ram()
+    }
+
+    pub fn import_save(&mut self, data: &[u8]) {
+        self.mmu.import_ram(data);
+    }
+
+    pub fn set_audio_sink(&mut self, sink: Box<dyn AudioSink>) {
+        self.mmu.set_audio_sink(sink);
+    }
+
+    /// The error that stopped the audio sink, if it failed. Emulation carries on without
+    /// sound, so the frontend should check for this.
+    pub fn audio_error(&self) -> Option<&std::io::Error> {
+        self.mmu.audio_error()
+    }
+
+    pub fn framebuffer(&self) -> &[u8; SCREEN_WIDTH * SCREEN_HEIGHT] {
+        self.mmu.framebuffer()
     }
 
     fn update_timers(&mut self) {
@@ -42,25 +151,52 @@ impl CPU {
         }
     }
 
-    fn handle_interrupt(&mut self) -> i32 {
+    /// Returns the machine cycles spent waking up and dispatching.
+    fn handle_interrupt(&mut self) -> u32 {
+        if self.locked_up.is_some() { return 0; }
         if !self.ime && !self.low_power_mode { return 0; }
 
         if !self.mmu.is_interrupt_waiting() { return 0; }
-        self.low_power_mode = false;
+        // leaving halt takes a cycle, even when ime is off and execution simply resumes
+        let mut elapsed = 0;
+        if self.low_power_mode {
+            self.low_power_mode = false;
+            self.cycle();
+            elapsed += 1;
+        }
 
-        if !self.ime { return 0; }
+        if !self.ime { return elapsed; }
         self.ime = false;
 
+        // after `ei; halt` hits the halt bug, the interrupt returns to the halt itself
+        if self.halt_bug {
+            self.halt_bug = false;
+            self.registers.pc = self.registers.pc.wrapping_sub(1);
+        }
+
         let interrupt = self.mmu.get_first_active_interrupt().unwrap();
         self.mmu.clear_interrupt(&interrupt);
+        // two internal cycles, the pushes, then one more to jump
+        self.cycle();
         self.push_stack(self.registers.pc);
         self.registers.pc = interrupt as u16;
-        4
+        elapsed + 5
+    }
+
+    /// Advances the hardware by the machine cycle a memory access takes, so that the rest
+    /// of the system sees accesses at the right point within an instruction.
+    fn cycle(&mut self) {
+        self.mmu.tick(1);
+        self.bus_cycles += 1;
     }
 
     fn fetch_byte(&mut self) -> u8 {
-        let value = self.mmu.read_memory(self.registers.pc);
-        self.registers.pc = self.registers.pc.wrapping_add(1);
+        let value = self.read_memory(self.registers.pc);
+        if self.halt_bug {
+            self.halt_bug = false;
+        } else {
+            self.registers.pc = self.registers.pc.wrapping_add(1);
+        }
         value
     }
 
@@ -79,18 +215,20 @@ impl CPU {
         if addr == 0x02DD || addr == 0xDD02 {
             print!("")
         }
+        self.cycle();
         self.mmu.write_memory(addr, value)
     }
 
-    fn read_memory(&self, addr: u16) -> u8 {
+    fn read_memory(&mut self, addr: u16) -> u8 {
+        self.cycle();
         self.mmu.read_memory(addr)
     }
 
     fn set_flags(&mut self, z: Option<bool>, n: Option<bool>, h: Option<bool>, c: Option<bool>) {
-        z.map(|z| self.registers.set_flag(CPUFlag::Z, z));
-        n.map(|n| self.registers.set_flag(CPUFlag::N, n));
-        h.map(|h| self.registers.set_flag(CPUFlag::H, h));
-        c.map(|c| self.registers.set_flag(CPUFlag::C, c));
+        if let Some(z) = z { self.registers.set_flag(CPUFlag::Z, z) }
+        if let Some(n) = n { self.registers.set_flag(CPUFlag::N, n) }
+        if let Some(h) = h { self.registers.set_flag(CPUFlag::H, h) }
+        if let Some(c) = c { self.registers.set_flag(CPUFlag::C, c) }
     }
 
     fn rotate_left_carry(&mut self, value: u8) -> u8 {
@@ -180,22 +318,21 @@ impl CPU {
         self.set_flags(Some(sub == 0), Some(true),
                        Some((value & 0xF) + carry > self.registers.a & 0xF),
                        Some((value as u16) + (carry as u16) > (self.registers.a as u16)));
-        Some((value as u16) + (carry as u16) > (self.registers.a as u16));
         self.registers.a = sub;
     }
 
     fn and_a(&mut self, value: u8) {
-        self.registers.a = self.registers.a & value;
+        self.registers.a &= value;
         self.set_flags(Some(self.registers.a == 0), Some(false), Some(true), Some(false));
     }
 
     fn or_a(&mut self, value: u8) {
-        self.registers.a = self.registers.a | value;
+        self.registers.a |= value;
         self.set_flags(Some(self.registers.a == 0), Some(false), Some(false), Some(false));
     }
 
     fn xor_a(&mut self, value: u8) {
-        self.registers.a = self.registers.a ^ value;
+        self.registers.a ^= value;
         self.set_flags(Some(self.registers.a == 0), Some(false), Some(false), Some(false));
     }
 
@@ -244,7 +381,9 @@ impl CPU {
         self.registers.pc = ((self.registers.pc as u32 as i32) + (offset as i32)) as u16;
     }
 
+    /// Pushes spend an internal cycle before the two writes.
     fn push_stack(&mut self, value: u16) {
+        self.cycle();
         self.registers.sp = self.registers.sp.wrapping_sub(1);
         self.write_memory(self.registers.sp, (value >> 8) as u8);
         self.registers.sp = self.registers.sp.wrapping_sub(1);
@@ -259,18 +398,27 @@ impl CPU {
         (high << 8) | low
     }
 
+    #[allow(dead_code)]
     fn memory_string(&self) -> String {
         format!("({:02X} {:02X} {:02X} {:02X})",
-                self.read_memory(self.registers.pc),
-                self.read_memory(self.registers.pc.wrapping_add(1)),
-                self.read_memory(self.registers.pc.wrapping_add(2)),
-                self.read_memory(self.registers.pc.wrapping_add(3)))
+                self.mmu.read_memory(self.registers.pc),
+                self.mmu.read_memory(self.registers.pc.wrapping_add(1)),
+                self.mmu.read_memory(self.registers.pc.wrapping_add(2)),
+                self.mmu.read_memory(self.registers.pc.wrapping_add(3)))
     }
 
-    fn execute(&mut self) -> u32 {
-        let opcode = Opcode::from(self.fetch_byte());
+    fn execute(&mut self) -> Result<u32, EmulationError> {
+        let pc = self.registers.pc;
+        let byte = self.fetch_byte();
+        let opcode = match Opcode::from(byte) {
+            Some(opcode) => opcode,
+            None => {
+                self.locked_up = Some(self.fault(EmulationErrorKind::IllegalOpcode, pc, byte, false));
+                return Ok(1);
+            }
+        };
 
-        match opcode {
+        let cycles = match opcode {
             Opcode::NOP => {
                 1
             }
@@ -311,7 +459,7 @@ impl CPU {
                 let second = (self.registers.sp >> 8) as u8;
                 let address = self.fetch_word();
                 self.write_memory(address, first);
-                self.write_memory(address + 1, second);
+                self.write_memory(address.wrapping_add(1), second);
                 5
             }
             Opcode::ADD_HL_BC => {
@@ -344,7 +492,29 @@ impl CPU {
                 self.registers.set_flag(CPUFlag::Z, false);
                 1
             }
-            Opcode::STOP => { 0 }
+            Opcode::STOP => {
+                // with a button already held STOP can't wait for one, so it degrades to a
+                // one byte nop or to HALT depending on whether an interrupt is pending
+                if self.mmu.is_joypad_line_low() {
+                    if self.mmu.is_interrupt_waiting() {
+                        return Ok(1);
+                    }
+                    self.fetch_byte();
+                    self.low_power_mode = true;
+                    return Ok(2);
+                }
+
+                // the byte after STOP is skipped
+                self.fetch_byte();
+                self.mmu.reset_divider();
+                if self.mmu.switch_speed() {
+                    // the CPU is paused while the clock settles at its new speed
+                    SPEED_SWITCH_CYCLES
+                } else {
+                    self.stopped = true;
+                    2
+                }
+            }
             Opcode::LD_DE_d16 => {
                 let (lower, upper) = self.fetch_split_word();
                 self.registers.d = upper;
@@ -414,7 +584,7 @@ impl CPU {
                     self.jump_relative();
                     3
                 } else {
-                    self.registers.pc += 1;
+                    self.fetch_byte();
                     2
                 }
             }
@@ -476,7 +646,7 @@ impl CPU {
                     self.jump_relative();
                     3
                 } else {
-                    self.registers.pc += 1;
+                    self.fetch_byte();
                     2
                 }
             }
@@ -517,7 +687,7 @@ impl CPU {
                     self.jump_relative();
                     3
                 } else {
-                    self.registers.pc += 1;
+                    self.fetch_byte();
                     2
                 }
             }
@@ -562,7 +732,7 @@ impl CPU {
                     self.jump_relative();
                     3
                 } else {
-                    self.registers.pc += 1;
+                    self.fetch_byte();
                     2
                 }
             }
@@ -599,7 +769,6 @@ impl CPU {
                 1
             }
             Opcode::LD_B_B => {
-                self.registers.b = self.registers.b;
                 1
             }
             Opcode::LD_B_C => {
@@ -635,7 +804,6 @@ impl CPU {
                 1
             }
             Opcode::LD_C_C => {
-                self.registers.c = self.registers.c;
                 1
             }
             Opcode::LD_C_D => {
@@ -671,7 +839,6 @@ impl CPU {
                 1
             }
             Opcode::LD_D_D => {
-                self.registers.d = self.registers.d;
                 1
             }
             Opcode::LD_D_E => {
@@ -707,7 +874,6 @@ impl CPU {
                 1
             }
             Opcode::LD_E_E => {
-                self.registers.e = self.registers.e;
                 1
             }
             Opcode::LD_E_H => {
@@ -743,7 +909,6 @@ impl CPU {
                 1
             }
             Opcode::LD_H_H => {
-                self.registers.h = self.registers.h;
                 1
             }
             Opcode::LD_H_L => {
@@ -779,7 +944,6 @@ impl CPU {
                 1
             }
             Opcode::LD_L_L => {
-                self.registers.l = self.registers.l;
                 1
             }
             Opcode::LD_L_rHL => {
@@ -815,7 +979,13 @@ impl CPU {
                 2
             }
             Opcode::HALT => {
-                self.low_power_mode = true;
+                // with ime off and an interrupt already pending the CPU doesn't halt at all.
+                // Instead it fails to advance pc past the next opcode, reading it twice.
+                if !self.ime && self.mmu.is_interrupt_waiting() {
+                    self.halt_bug = true;
+                } else {
+                    self.low_power_mode = true;
+                }
                 1
             }
             Opcode::LD_rHL_A => {
@@ -851,7 +1021,6 @@ impl CPU {
                 2
             }
             Opcode::LD_A_A => {
-                self.registers.a = self.registers.a;
                 1
             }
             Opcode::ADD_A_B => {
@@ -1125,6 +1294,8 @@ impl CPU {
                 1
             }
             Opcode::RET_NZ => {
+                // checking the condition takes an internal cycle
+                self.cycle();
                 if self.registers.get_flag(CPUFlag::Z) == 0 {
                     self.registers.pc = self.pop_stack();
                     5
@@ -1142,7 +1313,7 @@ impl CPU {
                     self.registers.pc = self.fetch_word();
                     4
                 } else {
-                    self.registers.pc += 2;
+                    self.fetch_word();
                     3
                 }
             }
@@ -1157,7 +1328,7 @@ impl CPU {
                     self.registers.pc = addr;
                     6
                 } else {
-                    self.registers.pc += 2;
+                    self.fetch_word();
                     3
                 }
             }
@@ -1168,7 +1339,7 @@ impl CPU {
             Opcode::ADD_A_d8 => {
                 let value = self.fetch_byte();
                 self.add_a(value, 0);
-                1
+                2
             }
             Opcode::RST_00H => {
                 self.push_stack(self.registers.pc);
@@ -1176,6 +1347,7 @@ impl CPU {
                 4
             }
             Opcode::RET_Z => {
+                self.cycle();
                 if self.registers.get_flag(CPUFlag::Z) == 1 {
                     self.registers.pc = self.pop_stack();
                     5
@@ -1192,11 +1364,11 @@ impl CPU {
                     self.registers.pc = self.fetch_word();
                     4
                 } else {
-                    self.registers.pc += 2;
+                    self.fetch_word();
                     3
                 }
             }
-            Opcode::PREFIX => { self.execute_extended() }
+            Opcode::PREFIX => { self.execute_extended()? }
             Opcode::CALL_Z_a16 => {
                 if self.registers.get_flag(CPUFlag::Z) == 1 {
                     let addr = self.fetch_word();
@@ -1204,7 +1376,7 @@ impl CPU {
                     self.registers.pc = addr;
                     6
                 } else {
-                    self.registers.pc += 2;
+                    self.fetch_word();
                     3
                 }
             }
@@ -1217,7 +1389,7 @@ impl CPU {
             Opcode::ADC_A_d8 => {
                 let value = self.fetch_byte();
                 self.add_a(value, self.registers.get_flag(CPUFlag::C));
-                1
+                2
             }
             Opcode::RST_08H => {
                 self.push_stack(self.registers.pc);
@@ -1225,6 +1397,7 @@ impl CPU {
                 4
             }
             Opcode::RET_NC => {
+                self.cycle();
                 if self.registers.get_flag(CPUFlag::C) == 0 {
                     self.registers.pc = self.pop_stack();
                     5
@@ -1242,7 +1415,7 @@ impl CPU {
                     self.registers.pc = self.fetch_word();
                     4
                 } else {
-                    self.registers.pc += 2;
+                    self.fetch_word();
                     3
                 }
             }
@@ -1253,7 +1426,7 @@ impl CPU {
                     self.registers.pc = addr;
                     6
                 } else {
-                    self.registers.pc += 2;
+                    self.fetch_word();
                     3
                 }
             }
@@ -1264,7 +1437,7 @@ impl CPU {
             Opcode::SUB_d8 => {
                 let value = self.fetch_byte();
                 self.sub_a(value, 0);
-                1
+                2
             }
             Opcode::RST_10H => {
                 self.push_stack(self.registers.pc);
@@ -1272,6 +1445,7 @@ impl CPU {
                 4
             }
             Opcode::RET_C => {
+                self.cycle();
                 if self.registers.get_flag(CPUFlag::C) == 1 {
                     self.registers.pc = self.pop_stack();
                     5
@@ -1289,7 +1463,7 @@ impl CPU {
                     self.registers.pc = self.fetch_word();
                     4
                 } else {
-                    self.registers.pc += 2;
+                    self.fetch_word();
                     3
                 }
             }
@@ -1300,14 +1474,14 @@ impl CPU {
                     self.registers.pc = addr;
                     6
                 } else {
-                    self.registers.pc += 2;
+                    self.fetch_word();
                     3
                 }
             }
             Opcode::SBC_A_d8 => {
                 let value = self.fetch_byte();
                 self.sub_a(value, self.registers.get_flag(CPUFlag::C));
-                1
+                2
             }
             Opcode::RST_18H => {
                 self.push_stack(self.registers.pc);
@@ -1315,7 +1489,7 @@ impl CPU {
                 4
             }
             Opcode::LDH_ra8_A => {
-                let addr = 0xFF00 as u16 + self.fetch_byte() as u16;
+                let addr = 0xFF00 + self.fetch_byte() as u16;
                 self.write_memory(addr, self.registers.a);
                 3
             }
@@ -1327,7 +1501,7 @@ impl CPU {
             Opcode::LD_rC_A => {
                 let addr = 0xFF00 | (self.registers.c as u16);
                 self.write_memory(addr, self.registers.a);
-                3
+                2
             }
             Opcode::PUSH_HL => {
                 self.push_stack(self.registers.hl());
@@ -1336,7 +1510,7 @@ impl CPU {
             Opcode::AND_d8 => {
                 let value = self.fetch_byte();
                 self.and_a(value);
-                1
+                2
             }
             Opcode::RST_20H => {
                 self.push_stack(self.registers.pc);
@@ -1359,7 +1533,7 @@ impl CPU {
             Opcode::XOR_d8 => {
                 let value = self.fetch_byte();
                 self.xor_a(value);
-                1
+                2
             }
             Opcode::RST_28H => {
                 self.push_stack(self.registers.pc);
@@ -1367,7 +1541,7 @@ impl CPU {
                 4
             }
             Opcode::LDH_A_ra8 => {
-                let addr = 0xFF00 as u16 + self.fetch_byte() as u16;
+                let addr = 0xFF00 + self.fetch_byte() as u16;
                 self.registers.a = self.read_memory(addr);
                 3
             }
@@ -1379,7 +1553,7 @@ impl CPU {
             Opcode::LD_A_rC => {
                 let addr = 0xFF00 | (self.registers.c as u16);
                 self.registers.a = self.read_memory(addr);
-                3
+                2
             }
             Opcode::DI => {
                 self.ime = false;
@@ -1392,7 +1566,7 @@ impl CPU {
             Opcode::OR_d8 => {
                 let value = self.fetch_byte();
                 self.or_a(value);
-                1
+                2
             }
             Opcode::RST_30H => {
                 self.push_stack(self.registers.pc);
@@ -1427,12 +1601,16 @@ impl CPU {
                 self.registers.pc = 0x38;
                 4
             }
-            _ => panic!("Unexpected opcode: {:#?}", opcode)
-        }
+            _ => return Err(self.fault(EmulationErrorKind::UnimplementedOpcode, pc, byte, false))
+        };
+        Ok(cycles)
     }
-    fn execute_extended(&mut self) -> u32 {
-        let opcode = Opcode::from_extended(self.fetch_byte());
-        match opcode {
+    fn execute_extended(&mut self) -> Result<u32, EmulationError> {
+        // the prefix byte itself was at pc - 1
+        let pc = self.registers.pc.wrapping_sub(1);
+        let byte = self.fetch_byte();
+        let opcode = Opcode::from_extended(byte);
+        let cycles = match opcode {
             Opcode::RLC_B => {
                 self.registers.b = self.rotate_left(self.registers.b);
                 2
@@ -2513,7 +2691,135 @@ impl CPU {
                 self.registers.a = self.set_bit(7, self.registers.a);
                 2
             }
-            _ => panic!("Unexpected opcode: {:#?}", opcode)
+            _ => return Err(self.fault(EmulationErrorKind::UnimplementedOpcode, pc, byte, true))
+        };
+        Ok(cycles)
+    }
+
+    fn fault(&self, kind: EmulationErrorKind, pc: u16, opcode: u8, extended: bool) -> EmulationError {
+        EmulationError {
+            kind,
+            pc,
+            bank: self.mmu.rom_bank(pc),
+            opcode,
+            extended,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::cartridge::test_rom;
+
+    /// A rom with no memory bank controller, with `code` at 0x0150 and `data` at 0x0200.
+    fn rom(code: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut rom = test_rom(0x00, 2);
+        rom[0x150..0x150 + code.len()].copy_from_slice(code);
+        rom[0x200..0x200 + data.len()].copy_from_slice(data);
+        rom
+    }
+
+    #[test]
+    fn serial_output_is_captured() {
+        let code = [
+            0x21, 0x00, 0x02, // ld hl, $0200
+            0x2A,             // next: ld a, [hl+]
+            0xA7,             // and a
+            0x28, 0xFE,       // done: jr z, done
+            0xE0, 0x01,       // ldh [$FF01], a
+            0x3E, 0x81,       // ld a, $81
+            0xE0, 0x02,       // ldh [$FF02], a
+            0xF0, 0x02,       // wait: ldh a, [$FF02]
+            0xCB, 0x7F,       // bit 7, a
+            0x20, 0xFA,       // jr nz, wait
+            0x18, 0xEE,       // jr next
+        ];
+        let mut cpu = CPU::new(&rom(&code, b"Passed\0")).unwrap();
+        let mut output = Vec::new();
+        for _ in 0..20_000 {
+            cpu.tick().unwrap();
+            output.extend(cpu.take_serial_output());
+        }
+        assert_eq!(output, b"Passed");
+    }
+
+    /// Runs the instruction at 0x0100 once with every flag clear and once with every flag
+    /// set, which takes each conditional branch one way and then the other.
+    fn cycles_with_flags_clear_and_set(bytes: &[u8]) -> [u32; 2] {
+        let mut rom = rom(&[], &[]);
+        rom[0x100..0x100 + bytes.len()].copy_from_slice(bytes);
+        let mut cycles = [0; 2];
+        for (flags, cycles) in [0x00, 0xF0].iter().zip(cycles.iter_mut()) {
+            let mut cpu = CPU::new(&rom).unwrap();
+            cpu.registers.f = *flags;
+            *cycles = cpu.execute().unwrap();
+            assert!(cpu.bus_cycles <= *cycles, "{:02X?} accessed memory more often than it takes cycles", bytes);
+        }
+        cycles
+    }
+
+    fn check_cycles(opcode: Opcode, bytes: &[u8]) {
+        let info = opcode.info();
+        let mut expected = [info.cycles as u32, info.branch_cycles.unwrap_or(info.cycles) as u32];
+        let mut cycles = cycles_with_flags_clear_and_set(bytes);
+        expected.sort_unstable();
+        cycles.sort_unstable();
+        assert_eq!(cycles, expected, "{} ({:02X?})", info.mnemonic, bytes);
+    }
+
+    #[test]
+    fn execute_matches_the_opcode_table() {
+        for byte in 0..=0xFF {
+            match Opcode::from(byte) {
+                // the extended opcodes are checked below
+                Some(Opcode::PREFIX) | None => {}
+                Some(opcode) => check_cycles(opcode, &[byte, 0x00, 0x00]),
+            }
+            check_cycles(Opcode::from_extended(byte), &[0xCB, byte]);
+        }
+    }
+
+    fn run_until_lock_up(cpu: &mut CPU) -> String {
+        for _ in 0..100 {
+            cpu.tick().unwrap();
+            if let Some(error) = cpu.lock_up() {
+                return error.to_string();
+            }
+        }
+        panic!("the CPU never locked up");
+    }
+
+    #[test]
+    fn faults_in_rom_name_the_bank() {
+        let mut cpu = CPU::new(&rom(&[0xD3], &[])).unwrap();
+        assert_eq!(run_until_lock_up(&mut cpu), "Illegal opcode D3 at 00:0150");
+    }
+
+    #[test]
+    fn faults_in_ram_have_no_bank() {
+        let code = [
+            0x3E, 0xD3,       // ld a, $D3
+            0xEA, 0x23, 0xC1, // ld [$C123], a
+            0xC3, 0x23, 0xC1, // jp $C123
+        ];
+        let mut cpu = CPU::new(&rom(&code, &[])).unwrap();
+        let error = run_until_lock_up(&mut cpu);
+        assert_eq!(error, "Illegal opcode D3 at C123");
+        assert_eq!(cpu.lock_up().unwrap().bank, None);
+    }
+
+    #[test]
+    fn storing_sp_at_ffff_wraps() {
+        let code = [
+            0x31, 0x34, 0x12, // ld sp, $1234
+            0x08, 0xFF, 0xFF, // ld [$FFFF], sp
+        ];
+        let mut cpu = CPU::new(&rom(&code, &[])).unwrap();
+        for _ in 0..4 {
+            cpu.tick().unwrap();
         }
+        assert_eq!(cpu.mmu.read_memory(0xFFFF), 0x34);
     }
-}
\ No newline at end of file
+}
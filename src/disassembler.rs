@@ -0,0 +1,345 @@
+use std::io::Write;
+use crate::opcode::{Opcode, OpcodeInfo};
+
+const BANK_SIZE: usize = 0x4000;
+const HEADER_START: u16 = 0x104;
+const HEADER_END: u16 = 0x150;
+const DATA_BYTES_PER_LINE: usize = 4;
+// runs of padding at least this long are collapsed into a single line
+const MIN_FILL_RUN: usize = 16;
+
+/// Where execution can start without anything jumping there, all in bank 0. The RST
+/// vectors are only labelled, and traced once an RST instruction is found.
+const VECTORS: [(u16, &str); 14] = [
+    (0x00, "rst_00"),
+    (0x08, "rst_08"),
+    (0x10, "rst_10"),
+    (0x18, "rst_18"),
+    (0x20, "rst_20"),
+    (0x28, "rst_28"),
+    (0x30, "rst_30"),
+    (0x38, "rst_38"),
+    (0x40, "vblank"),
+    (0x48, "lcd_stat"),
+    (0x50, "timer"),
+    (0x58, "serial"),
+    (0x60, "joypad"),
+    (0x100, "entry"),
+];
+
+struct Instruction<'a> {
+    opcode: Opcode,
+    info: &'static OpcodeInfo,
+    bytes: &'a [u8],
+}
+
+/// Splits a rom into code and data by tracing every path reachable from the vectors, then
+/// lists it bank by bank.
+pub struct Disassembler<'a> {
+    rom: &'a [u8],
+    // whether an instruction starts at each rom offset
+    code: Vec<bool>,
+}
+
+impl<'a> Disassembler<'a> {
+    pub fn new(rom: &'a [u8]) -> Disassembler<'a> {
+        let mut disassembler = Disassembler {
+            rom,
+            code: vec![false; rom.len()],
+        };
+        disassembler.trace();
+        disassembler
+    }
+
+    fn bank_count(&self) -> usize {
+        self.rom.len().div_ceil(BANK_SIZE)
+    }
+
+    /// The address a rom offset is mapped at, with bank 0 fixed at 0x0000 and every other
+    /// bank switched into 0x4000.
+    fn address(offset: usize) -> u16 {
+        if offset < BANK_SIZE {
+            offset as u16
+        } else {
+            (BANK_SIZE + offset % BANK_SIZE) as u16
+        }
+    }
+
+    /// The rom offset an address maps to, or None if the address is outside the window the
+    /// bank is mapped at.
+    fn offset(bank: usize, address: u16) -> Option<usize> {
+        let start = if bank == 0 { 0 } else { BANK_SIZE };
+        let address = address as usize;
+        if address < start || address >= start + BANK_SIZE {
+            return None;
+        }
+        Some(bank * BANK_SIZE + address - start)
+    }
+
+    fn is_header(bank: usize, address: u16) -> bool {
+        bank == 0 && (HEADER_START..HEADER_END).contains(&address)
+    }
+
+    /// Decodes the instruction at a rom offset, or None for illegal opcodes and
+    /// instructions cut off by the end of their bank.
+    fn decode(&self, offset: usize) -> Option<Instruction<'a>> {
+        let opcode = match Opcode::from(self.rom[offset]) {
+            Some(Opcode::PREFIX) => Opcode::from_extended(*self.rom.get(offset + 1)?),
+            opcode => opcode?,
+        };
+        let info = opcode.info();
+        let end = offset + info.length as usize;
+        if end > self.rom.len() || (end - 1) / BANK_SIZE != offset / BANK_SIZE {
+            return None;
+        }
+        Some(Instruction { opcode, info, bytes: &self.rom[offset..end] })
+    }
+
+    /// Marks everything reachable from the entry point and interrupt vectors as code,
+    /// following jumps and calls.
+    fn trace(&mut self) {
+        // each path remembers which bank it expects at 0x4000, if it can tell
+        let initial_bank = if self.bank_count() == 2 { Some(1) } else { None };
+        let mut pending: Vec<(usize, u16, Option<usize>)> = VECTORS.iter()
+            .filter(|(address, _)| *address >= 0x40)
+            .map(|(address, _)| (0, *address, initial_bank))
+            .collect();
+
+        while let Some((bank, mut address, mut mapped_bank)) = pending.pop() {
+            let mut loaded_value = None;
+            // running off the end of the bank ends the path rather than wrapping around
+            while let Some(offset) = Self::offset(bank, address) {
+                if offset >= self.rom.len() || self.code[offset] || Self::is_header(bank, address) {
+                    break;
+                }
+                // padding is 0xFF, which decodes as endless RST 38H. Real code hardly ever
+                // uses that one, so it's taken as running off the end of the code instead.
+                if self.rom[offset] == 0xFF {
+                    break;
+                }
+                let instruction = match self.decode(offset) {
+                    Some(instruction) => instruction,
+                    None => break,
+                };
+                self.code[offset] = true;
+
+                // the usual `ld a, n` then `ld [$2000], a` bank switch
+                let bytes = instruction.bytes;
+                if let (Opcode::LD_ra16_A, Some(value)) = (instruction.opcode, loaded_value) {
+                    if (0x2000..0x4000).contains(&(bytes[1] as u16 | (bytes[2] as u16) << 8)) {
+                        mapped_bank = Some((value as usize).max(1));
+                    }
+                }
+                loaded_value = match instruction.opcode {
+                    Opcode::LD_A_d8 => Some(bytes[1]),
+                    _ => None
+                };
+
+                let next = address.wrapping_add(bytes.len() as u16);
+                if let Some(target) = Self::branch_target(&instruction, next) {
+                    if let Some(target_bank) = self.resolve_bank(bank, target, mapped_bank) {
+                        pending.push((target_bank, target, mapped_bank));
+                    }
+                }
+                if Self::ends_flow(instruction.opcode) {
+                    break;
+                }
+                address = next;
+            }
+        }
+    }
+
+    fn branch_target(instruction: &Instruction, next: u16) -> Option<u16> {
+        let bytes = instruction.bytes;
+        match instruction.opcode {
+            Opcode::JP_a16 | Opcode::JP_NZ_a16 | Opcode::JP_Z_a16 | Opcode::JP_NC_a16 | Opcode::JP_C_a16 |
+            Opcode::CALL_a16 | Opcode::CALL_NZ_a16 | Opcode::CALL_Z_a16 | Opcode::CALL_NC_a16 | Opcode::CALL_C_a16 =>
+                Some(bytes[1] as u16 | (bytes[2] as u16) << 8),
+            Opcode::JR_r8 | Opcode::JR_NZ_r8 | Opcode::JR_Z_r8 | Opcode::JR_NC_r8 | Opcode::JR_C_r8 =>
+                Some(next.wrapping_add(bytes[1] as i8 as u16)),
+            Opcode::RST_00H | Opcode::RST_08H | Opcode::RST_10H | Opcode::RST_18H |
+            Opcode::RST_20H | Opcode::RST_28H | Opcode::RST_30H | Opcode::RST_38H =>
+                Some((bytes[0] & 0x38) as u16),
+            _ => None
+        }
+    }
+
+    /// Execution never falls through these to the next instruction.
+    fn ends_flow(opcode: Opcode) -> bool {
+        matches!(opcode, Opcode::JP_a16 | Opcode::JP_HL | Opcode::JR_r8 | Opcode::RET | Opcode::RETI)
+    }
+
+    /// Which bank a jump from `bank` to `address` lands in, if it can be known statically.
+    /// Code in bank 0 can reach any bank at 0x4000, so those targets are only followed when
+    /// the bank switch that precedes them was spotted.
+    fn resolve_bank(&self, bank: usize, address: u16, mapped_bank: Option<usize>) -> Option<usize> {
+        match address {
+            0x0000 ..= 0x3FFF => Some(0),
+            0x4000 ..= 0x7FFF if bank != 0 => Some(bank),
+            0x4000 ..= 0x7FFF => mapped_bank.filter(|mapped_bank| *mapped_bank < self.bank_count()),
+            // ram
+            _ => None
+        }
+    }
+
+    /// Fills the operand placeholders of the mnemonic with the instruction's bytes.
+    fn format(instruction: &Instruction, next: u16) -> String {
+        let bytes = instruction.bytes;
+        let mnemonic = instruction.info.mnemonic;
+        if instruction.opcode.is_extended() {
+            return mnemonic.to_string();
+        }
+        match instruction.opcode {
+            Opcode::JR_r8 | Opcode::JR_NZ_r8 | Opcode::JR_Z_r8 | Opcode::JR_NC_r8 | Opcode::JR_C_r8 =>
+                return mnemonic.replace("r8", &format!("${:04X}", next.wrapping_add(bytes[1] as i8 as u16))),
+            Opcode::ADD_SP_r8 => return mnemonic.replace("r8", &format!("{}", bytes[1] as i8)),
+            Opcode::LD_HL_SPI => return mnemonic.replace("+r8", &format!("{:+}", bytes[1] as i8)),
+            _ => {}
+        }
+        // operands are little endian
+        let operand = bytes[1..].iter().rev().fold(0u16, |operand, byte| operand << 8 | *byte as u16);
+        mnemonic
+            .replace("d16", &format!("${:04X}", operand))
+            .replace("a16", &format!("${:04X}", operand))
+            .replace("d8", &format!("${:02X}", operand))
+            .replace("a8", &format!("$FF{:02X}", operand))
+    }
+
+    fn label(bank: usize, address: u16) -> Option<&'static str> {
+        if bank != 0 {
+            return None;
+        }
+        if address == HEADER_START {
+            return Some("header");
+        }
+        VECTORS.iter().find(|(vector, _)| *vector == address).map(|(_, label)| *label)
+    }
+
+    /// Writes one `BB:AAAA  bytes  mnemonic` line per instruction, with data in between.
+    pub fn write_to(&self, out: &mut impl Write) -> std::io::Result<()> {
+        for bank in 0..self.bank_count() {
+            writeln!(out, "; bank {:02X}", bank)?;
+            let end = ((bank + 1) * BANK_SIZE).min(self.rom.len());
+            let mut offset = bank * BANK_SIZE;
+            while offset < end {
+                let address = Self::address(offset);
+                if let Some(label) = Self::label(bank, address) {
+                    writeln!(out, "{}:", label)?;
+                }
+
+                if self.code[offset] {
+                    if let Some(instruction) = self.decode(offset) {
+                        let next = address.wrapping_add(instruction.bytes.len() as u16);
+                        writeln!(out, "{:02X}:{:04X}  {:<11}  {}", bank, address,
+                                 Self::hex(instruction.bytes), Self::format(&instruction, next))?;
+                        offset += instruction.bytes.len();
+                        if Self::ends_flow(instruction.opcode) {
+                            writeln!(out)?;
+                        }
+                        continue;
+                    }
+                }
+                offset += self.write_data(out, bank, offset, end)?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    /// Writes a line of data starting at `offset`, returning how many bytes it covered.
+    fn write_data(&self, out: &mut impl Write, bank: usize, offset: usize, end: usize) -> std::io::Result<usize> {
+        let address = Self::address(offset);
+        let fill = self.fill_length(bank, offset, end);
+        if fill >= MIN_FILL_RUN {
+            writeln!(out, "{:02X}:{:04X}  {:<11}  ds {}, ${:02X}", bank, address, Self::hex(&self.rom[offset..offset + 1]), fill, self.rom[offset])?;
+            return Ok(fill);
+        }
+
+        // a line ends early at the next instruction, label or run of padding
+        let mut length = 1;
+        while length < DATA_BYTES_PER_LINE && offset + length < end && self.is_data(bank, offset + length)
+            && self.fill_length(bank, offset + length, end) < MIN_FILL_RUN {
+            length += 1;
+        }
+        let bytes = &self.rom[offset..offset + length];
+        let values = bytes.iter().map(|byte| format!("${:02X}", byte)).collect::<Vec<_>>().join(",");
+        writeln!(out, "{:02X}:{:04X}  {:<11}  db {}", bank, address, Self::hex(bytes), values)?;
+        Ok(length)
+    }
+
+    fn is_data(&self, bank: usize, offset: usize) -> bool {
+        !self.code[offset] && Self::label(bank, Self::address(offset)).is_none()
+    }
+
+    /// How many times the byte at `offset` repeats before the data ends.
+    fn fill_length(&self, bank: usize, offset: usize, end: usize) -> usize {
+        let value = self.rom[offset];
+        let mut length = 1;
+        while offset + length < end && self.rom[offset + length] == value && self.is_data(bank, offset + length) {
+            length += 1;
+        }
+        length
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02X}", byte)).collect::<Vec<_>>().join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::test_rom;
+
+    #[test]
+    fn lists_code_data_and_labels() {
+        let mut rom = test_rom(0x01, 4);
+        rom[0x150..0x160].copy_from_slice(&[
+            0x18, 0x02,       // jr $0154
+            0xAB, 0xCD,       // data
+            0xF8, 0xFE,       // ld hl, sp-2
+            0xE0, 0x80,       // ldh [$FF80], a
+            0x3E, 0x01,       // ld a, $01
+            0xEA, 0x00, 0x20, // ld [$2000], a
+            0xC3, 0x00, 0x40, // jp $4000
+        ]);
+        // jr $4000, only reachable through the bank switch
+        rom[0x4000..0x4002].copy_from_slice(&[0x18, 0xFE]);
+
+        let mut out = Vec::new();
+        Disassembler::new(&rom).write_to(&mut out).unwrap();
+        let listing = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = listing.lines().collect();
+        for expected in [
+            "entry:",
+            "00:0100  00           NOP",
+            "00:0101  C3 50 01     JP $0150",
+            "header:",
+            "00:0150  18 02        JR $0154",
+            "00:0152  AB CD        db $AB,$CD",
+            "00:0154  F8 FE        LD HL,SP-2",
+            "00:0156  E0 80        LDH ($FF80),A",
+            "00:0158  3E 01        LD A,$01",
+            "00:015A  EA 00 20     LD ($2000),A",
+            "00:015D  C3 00 40     JP $4000",
+            "; bank 01",
+            "01:4000  18 FE        JR $4000",
+            "01:4002  00           ds 16382, $00",
+            // nothing switches to bank 2, so it's all data
+            "02:4000  02           db $02",
+            "02:4001  00           ds 16383, $00",
+        ].iter() {
+            assert!(lines.contains(expected), "missing {:?} in:\n{}", expected, listing);
+        }
+    }
+
+    #[test]
+    fn trace_stops_at_the_end_of_a_bank() {
+        let mut rom = vec![0; 2 * BANK_SIZE];
+        // nop, jp $7FFE, which runs two nops into the end of bank 1
+        rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0xFE, 0x7F]);
+        let disassembler = Disassembler::new(&rom);
+        assert!(disassembler.code[0x7FFE] && disassembler.code[0x7FFF]);
+        assert!(!disassembler.code[BANK_SIZE]);
+    }
+}
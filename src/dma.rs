@@ -0,0 +1,62 @@
+const TRANSFER_LENGTH: u16 = 0xA0;
+
+pub struct DMA {
+    source: u8, // dma, the high byte of the source address
+    index: u16,
+    // the transfer starts one machine cycle after the register is written
+    delay: u8,
+    active: bool,
+}
+
+impl DMA {
+    pub fn new() -> DMA {
+        DMA {
+            source: 0xFF,
+            index: 0,
+            delay: 0,
+            active: false,
+        }
+    }
+
+    pub fn read_byte(&self) -> u8 {
+        self.source
+    }
+
+    pub fn write_byte(&mut self, value: u8) {
+        self.source = value;
+        self.index = 0;
+        self.delay = 1;
+        self.active = true;
+    }
+
+    /// Whether a transfer currently owns the bus, which locks the CPU out of everything but
+    /// the 0xFF00 page.
+    pub fn is_transferring(&self) -> bool {
+        self.active && self.delay == 0
+    }
+
+    /// Advances the transfer by one machine cycle, returning the source and destination
+    /// addresses of the byte that should be copied during it.
+    pub fn tick(&mut self) -> Option<(u16, u16)> {
+        if !self.active {
+            return None;
+        }
+        if self.delay > 0 {
+            self.delay -= 1;
+            return None;
+        }
+
+        let mut source = (self.source as u16) << 8 | self.index;
+        // sources past working ram read from its echo, like the CPU would
+        if source >= 0xE000 {
+            source -= 0x2000;
+        }
+        let destination = 0xFE00 | self.index;
+
+        self.index += 1;
+        if self.index == TRANSFER_LENGTH {
+            self.active = false;
+        }
+        Some((source, destination))
+    }
+}
@@ -0,0 +1,66 @@
+use std::fmt::{Display, Formatter};
+
+/// Why a rom could not be turned into a running emulator.
+#[derive(Debug)]
+pub enum LoadError {
+    /// The rom is too small to contain a cartridge header.
+    MissingHeader,
+    /// The header names a memory bank controller that isn't emulated.
+    UnsupportedCartridge { cartridge_type: u8, name: &'static str },
+    /// The boot rom is neither the 256 byte DMG one nor the 2304 byte CGB one.
+    InvalidBootRom { size: usize },
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::MissingHeader =>
+                write!(f, "ROM is too small to contain a cartridge header"),
+            LoadError::UnsupportedCartridge { cartridge_type, name } =>
+                write!(f, "Unsupported cartridge type {:02X} ({})", cartridge_type, name),
+            LoadError::InvalidBootRom { size } =>
+                write!(f, "Boot ROM should be 256 (DMG) or 2304 (CGB) bytes, not {}", size),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+#[derive(Debug)]
+pub enum EmulationErrorKind {
+    /// One of the bytes that doesn't decode to any instruction. These lock up the CPU rather
+    /// than stopping emulation, see `CPU::lock_up`.
+    IllegalOpcode,
+    /// A valid instruction the CPU has no implementation for.
+    UnimplementedOpcode,
+}
+
+/// A fault hit while executing, pointing at the instruction that caused it.
+#[derive(Debug)]
+pub struct EmulationError {
+    pub kind: EmulationErrorKind,
+    pub pc: u16,
+    /// The cartridge rom bank mapped at pc when the fault happened, or None when pc was in
+    /// ram or the boot rom.
+    pub bank: Option<usize>,
+    pub opcode: u8,
+    /// Whether the opcode followed a 0xCB prefix.
+    pub extended: bool,
+}
+
+impl Display for EmulationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let description = match self.kind {
+            EmulationErrorKind::IllegalOpcode => "Illegal opcode",
+            EmulationErrorKind::UnimplementedOpcode => "Unimplemented opcode",
+        };
+        let prefix = if self.extended { "CB " } else { "" };
+        write!(f, "{} {}{:02X} at ", description, prefix, self.opcode)?;
+        match self.bank {
+            Some(bank) => write!(f, "{:02X}:{:04X}", bank, self.pc),
+            None => write!(f, "{:04X}", self.pc),
+        }
+    }
+}
+
+impl std::error::Error for EmulationError {}
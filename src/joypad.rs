@@ -0,0 +1,85 @@
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    // bits 0-3 are the direction keys, bits 4-7 the action buttons
+    fn get_mask(&self) -> u8 {
+        match self {
+            Button::Right => 0b0000_0001,
+            Button::Left => 0b0000_0010,
+            Button::Up => 0b0000_0100,
+            Button::Down => 0b0000_1000,
+            Button::A => 0b0001_0000,
+            Button::B => 0b0010_0000,
+            Button::Select => 0b0100_0000,
+            Button::Start => 0b1000_0000,
+        }
+    }
+}
+
+pub struct Joypad {
+    select: u8, // bits 4-5 of p1, active low
+    pressed: u8,
+}
+
+impl Joypad {
+    pub fn new() -> Joypad {
+        Joypad {
+            select: 0b0011_0000,
+            pressed: 0,
+        }
+    }
+
+    pub fn read_byte(&self) -> u8 {
+        0b1100_0000 | self.select | self.input_lines()
+    }
+
+    /// Returns true if the write pulled one of the input lines low.
+    pub fn write_byte(&mut self, value: u8) -> bool {
+        let previous = self.input_lines();
+        self.select = value & 0b0011_0000;
+        Self::has_falling_edge(previous, self.input_lines())
+    }
+
+    /// Returns true if pressing the button pulled one of the input lines low.
+    pub fn press(&mut self, button: Button) -> bool {
+        let previous = self.input_lines();
+        self.pressed |= button.get_mask();
+        Self::has_falling_edge(previous, self.input_lines())
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.pressed &= !button.get_mask();
+    }
+
+    /// Whether a held button in a selected group is pulling an input line low, which is
+    /// what wakes the CPU from STOP.
+    pub fn is_any_line_low(&self) -> bool {
+        self.input_lines() != 0x0F
+    }
+
+    /// The low nibble of p1, where a pressed button in a selected group reads as 0.
+    fn input_lines(&self) -> u8 {
+        let mut lines = 0;
+        if self.select & 0b0001_0000 == 0 {
+            lines |= self.pressed & 0x0F;
+        }
+        if self.select & 0b0010_0000 == 0 {
+            lines |= self.pressed >> 4;
+        }
+        !lines & 0x0F
+    }
+
+    fn has_falling_edge(previous: u8, current: u8) -> bool {
+        previous & !current != 0
+    }
+}
@@ -0,0 +1,19 @@
+#![allow(clippy::upper_case_acronyms)]
+#![allow(clippy::new_without_default)]
+
+mod register;
+pub mod cpu;
+pub mod opcode;
+pub mod mmu;
+mod interupt;
+mod timer;
+mod memory_bank;
+pub mod ppu;
+mod dma;
+pub mod joypad;
+pub mod apu;
+pub mod audio;
+pub mod serial;
+pub mod cartridge;
+pub mod error;
+pub mod disassembler;
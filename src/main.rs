@@ -1,18 +1,188 @@
-mod register;
-mod cpu;
-mod opcode;
-mod mmu;
-mod interupt;
-mod timer;
-mod memory_bank;
+use game_boy::cpu::CPU;
+use game_boy::audio::WavWriter;
+use game_boy::cartridge::Cartridge;
+use game_boy::disassembler::Disassembler;
+use game_boy::error::LoadError;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use cpu::CPU;
+const DEFAULT_SAMPLE_RATE: u32 = 44100;
+// two seconds of emulated time at normal speed, in machine cycles
+const SAVE_INTERVAL: u64 = 2 * 1_048_576;
+
+/// Keeps a .sav file next to the rom in sync with the cartridge's battery-backed ram.
+struct SaveFile {
+    path: PathBuf,
+    contents: Vec<u8>,
+}
+
+impl SaveFile {
+    fn load(path: PathBuf, cpu: &mut CPU) -> SaveFile {
+        if let Ok(contents) = std::fs::read(&path) {
+            cpu.import_save(&contents);
+        }
+        SaveFile { path, contents: cpu.export_save() }
+    }
+
+    /// Writes the save out, but only if the game changed it since the last write.
+    fn update(&mut self, cpu: &CPU) {
+        let contents = cpu.export_save();
+        if contents != self.contents {
+            std::fs::write(&self.path, &contents).unwrap();
+            self.contents = contents;
+        }
+    }
+}
+
+/// Prints the header of every rom given, to audit collections without running anything.
+fn print_info(paths: impl Iterator<Item = String>) {
+    for path in paths {
+        println!("{}", path);
+        let rom = match std::fs::read(&path) {
+            Ok(rom) => rom,
+            Err(error) => {
+                println!("Failed to read: {}\n", error);
+                continue;
+            }
+        };
+        match Cartridge::parse(&rom) {
+            Some(cartridge) => println!("{}\n", cartridge),
+            None => println!("Too small to contain a cartridge header\n"),
+        }
+    }
+}
+
+/// Reads a file the emulator can't go on without, exiting with the error otherwise.
+fn read_or_exit(path: &str) -> Vec<u8> {
+    match std::fs::read(path) {
+        Ok(contents) => contents,
+        Err(error) => {
+            eprintln!("Failed to read {}: {}", path, error);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Lists the code and data of a rom, bank by bank.
+fn print_disassembly(path: &str) {
+    let rom = read_or_exit(path);
+    let stdout = std::io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    // a closed pipe, like when piping into head, just ends the listing
+    let _ = Disassembler::new(&rom).write_to(&mut out).and_then(|_| out.flush());
+}
 
 fn main() {
-    let rom = std::fs::read("rom").unwrap();
-    let mut cpu = CPU::new(&rom);
-    cpu.mmu.stub();
-    loop {
-        cpu.tick();
+    match std::env::args().nth(1).as_deref() {
+        Some("info") => {
+            print_info(std::env::args().skip(2));
+            return;
+        }
+        Some("disasm") => {
+            print_disassembly(&std::env::args().nth(2).expect("Missing rom path for disasm"));
+            return;
+        }
+        _ => {}
+    }
+
+    let mut rom_path = String::from("rom");
+    let mut audio_path = None;
+    let mut boot_rom_path = None;
+    let mut sample_rate = DEFAULT_SAMPLE_RATE;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record-audio" => audio_path = Some(args.next().expect("Missing path for --record-audio")),
+            "--boot-rom" => boot_rom_path = Some(args.next().expect("Missing path for --boot-rom")),
+            "--sample-rate" => {
+                sample_rate = args.next()
+                    .and_then(|rate| rate.parse().ok())
+                    .expect("Missing or invalid value for --sample-rate")
+            }
+            _ => rom_path = arg,
+        }
+    }
+
+    let rom = read_or_exit(&rom_path);
+    let cpu = match boot_rom_path.as_deref() {
+        Some(path) => CPU::with_boot_rom(&rom, &read_or_exit(path)),
+        None => CPU::new(&rom),
+    };
+    let mut cpu = match cpu {
+        Ok(cpu) => cpu,
+        Err(error) => {
+            let path = match (&error, boot_rom_path.as_deref()) {
+                (LoadError::InvalidBootRom { .. }, Some(boot_rom_path)) => boot_rom_path,
+                _ => &rom_path,
+            };
+            eprintln!("Failed to load {}: {}", path, error);
+            std::process::exit(1);
+        }
+    };
+    if let Some(path) = audio_path {
+        match WavWriter::create(&path, sample_rate) {
+            Ok(wav_writer) => cpu.set_audio_sink(Box::new(wav_writer)),
+            Err(error) => {
+                eprintln!("Failed to create {}: {}", path, error);
+                std::process::exit(1);
+            }
+        }
+    }
+    let mut save_file = if cpu.cartridge().has_battery() {
+        Some(SaveFile::load(PathBuf::from(&rom_path).with_extension("sav"), &mut cpu))
+    } else {
+        None
+    };
+
+    // Ctrl-C and SIGTERM only stop the loop, so the latest save still gets written
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let handler_flag = interrupted.clone();
+    ctrlc::set_handler(move || handler_flag.store(true, Ordering::Relaxed))
+        .expect("Failed to install the termination signal handler");
+
+    let mut stdout = std::io::stdout();
+    let mut next_save = SAVE_INTERVAL;
+    // the way out is a signal, a fault, a lock up or a failed recording, which all get the
+    // latest save written
+    let error = loop {
+        if interrupted.load(Ordering::Relaxed) {
+            break None;
+        }
+        if let Err(error) = cpu.tick() {
+            break Some(format!("Emulation stopped: {}", error));
+        }
+        // a real console would just hang, but there's nothing left to show
+        if let Some(error) = cpu.lock_up() {
+            break Some(format!("CPU locked up: {}", error));
+        }
+        if let Some(error) = cpu.audio_error() {
+            break Some(format!("Audio recording failed: {}", error));
+        }
+        // test ROMs report their results over the serial port
+        let output = cpu.take_serial_output();
+        if !output.is_empty() {
+            stdout.write_all(&output).unwrap();
+            stdout.flush().unwrap();
+        }
+
+        if cpu.cycles() >= next_save {
+            next_save = cpu.cycles() + SAVE_INTERVAL;
+            if let Some(save_file) = save_file.as_mut() {
+                save_file.update(&cpu);
+            }
+        }
+    };
+
+    if let Some(save_file) = save_file.as_mut() {
+        save_file.update(&cpu);
+    }
+    // exiting skips destructors, and dropping the audio sink finishes the recording
+    drop(cpu);
+    if let Some(error) = error {
+        eprintln!("{}", error);
+        std::process::exit(1);
     }
 }
@@ -1,47 +1,169 @@
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::cartridge::Cartridge;
+use crate::error::LoadError;
 
+pub trait MemoryBank {
+    fn read_memory(&self, address: u16) -> u8;
+    fn write_memory(&mut self, address: u16, value: u8);
+    /// The rom bank currently mapped at the given address.
+    fn rom_bank(&self, address: u16) -> usize;
+    /// Advances any hardware on the cartridge itself, such as a real-time clock.
+    fn tick(&mut self, _elapsed: u32) {}
+    /// Drains the rumble motor state changes since the last call, true meaning it turned on.
+    /// Only the latest few hundred are kept in between.
+    fn take_rumble_events(&mut self) -> Vec<bool> { Vec::new() }
+    /// The contents of the external ram, in the layout used by .sav files.
+    fn export_ram(&self) -> Vec<u8>;
+    fn import_ram(&mut self, data: &[u8]);
+}
 
+/// Copies as much of a save file as fits, so saves from other emulators with a slightly
+/// different size still load.
+fn import_bytes(destination: &mut [u8], data: &[u8]) {
+    let length = destination.len().min(data.len());
+    destination[..length].copy_from_slice(&data[..length]);
+}
 
+const ROM_BANK_SIZE: usize = 0x4000;
+const RAM_BANK_SIZE: usize = 0x2000;
 
+/// The rom and external ram of a cartridge, allocated to the sizes its header declares.
+/// The offsets of the currently mapped banks are kept up to date on every bank switch, so
+/// reads are a single index.
+struct Storage {
+    rom: Box<[u8]>,
+    ram: Box<[u8]>,
+    rom_bank_count: usize,
+    ram_bank_count: usize,
+    // ram smaller than a bank, like MBC2's or 2 KB chips, is echoed across the whole bank
+    ram_mask: usize,
+    low_rom_offset: usize, // 0x0000-0x3FFF
+    high_rom_offset: usize, // 0x4000-0x7FFF
+    ram_offset: usize, // 0xA000-0xBFFF
+}
 
-pub trait MemoryBank {
-    fn read_memory(&self, address: u16) -> u8;
-    fn write_memory(&mut self, address: u16, value: u8);
+impl Storage {
+    fn new(rom: &[u8], ram_size: usize) -> Storage {
+        Self::with_rom_size(rom, rom.len(), ram_size)
+    }
+
+    /// Uses the sizes declared in the header, though never truncates the rom if the header
+    /// claims it is smaller than it actually is.
+    fn from_header(rom: &[u8], cartridge: &Cartridge) -> Storage {
+        let rom_size = cartridge.rom_size().unwrap_or(0).max(rom.len());
+        Self::with_rom_size(rom, rom_size, cartridge.ram_size().unwrap_or(0))
+    }
+
+    fn with_rom_size(rom: &[u8], rom_size: usize, ram_size: usize) -> Storage {
+        // round up to a power of two number of banks so bank numbers can simply be masked
+        let rom_bank_count = rom_size.div_ceil(ROM_BANK_SIZE).next_power_of_two().max(2);
+        let mut padded_rom = rom.to_vec();
+        padded_rom.resize(rom_bank_count * ROM_BANK_SIZE, 0xFF);
+
+        Storage {
+            rom: padded_rom.into_boxed_slice(),
+            ram: vec![0; ram_size].into_boxed_slice(),
+            rom_bank_count,
+            ram_bank_count: ram_size.div_ceil(RAM_BANK_SIZE).max(1),
+            ram_mask: ram_size.clamp(1, RAM_BANK_SIZE) - 1,
+            low_rom_offset: 0,
+            high_rom_offset: ROM_BANK_SIZE,
+            ram_offset: 0,
+        }
+    }
+
+    fn read_rom(&self, address: u16) -> u8 {
+        match address {
+            0x0000 ..= 0x3FFF => self.rom[self.low_rom_offset + address as usize],
+            _ => self.rom[self.high_rom_offset + (address as usize & (ROM_BANK_SIZE - 1))]
+        }
+    }
+
+    /// Carts without ram leave the bus floating, which reads as 0xFF.
+    fn read_ram(&self, address: u16) -> u8 {
+        if self.ram.is_empty() {
+            return 0xFF;
+        }
+        self.ram[self.ram_offset + (address as usize & self.ram_mask)]
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if self.ram.is_empty() {
+            return;
+        }
+        self.ram[self.ram_offset + (address as usize & self.ram_mask)] = value;
+    }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        match address {
+            0x0000 ..= 0x3FFF => self.low_rom_offset / ROM_BANK_SIZE,
+            _ => self.high_rom_offset / ROM_BANK_SIZE
+        }
+    }
+
+    fn map_low_rom(&mut self, bank: usize) {
+        self.low_rom_offset = (bank & (self.rom_bank_count - 1)) * ROM_BANK_SIZE;
+    }
+
+    fn map_high_rom(&mut self, bank: usize) {
+        self.high_rom_offset = (bank & (self.rom_bank_count - 1)) * ROM_BANK_SIZE;
+    }
+
+    fn map_ram(&mut self, bank: usize) {
+        self.ram_offset = (bank % self.ram_bank_count) * RAM_BANK_SIZE;
+    }
+
+    fn export_ram(&self) -> Vec<u8> {
+        self.ram.to_vec()
+    }
+
+    fn import_ram(&mut self, data: &[u8]) {
+        import_bytes(&mut self.ram, data);
+    }
 }
 
 struct MemoryBankZero {
-    rom: [u8; 0x8000],
-    ram: [u8; 0x2000]
+    storage: Storage,
 }
 
 impl MemoryBankZero {
-    fn new(rom: &[u8]) -> MemoryBankZero {
-        let mut memory_bank = Self {
-            rom: [0; 0x8000],
-            ram: [0; 0x2000]
-        };
-        for (i, byte) in rom.iter().enumerate() {
-            memory_bank.rom[i] = *byte;
+    fn new(rom: &[u8], cartridge: &Cartridge) -> MemoryBankZero {
+        Self {
+            storage: Storage::from_header(rom, cartridge),
         }
-        memory_bank
     }
 }
 
 impl MemoryBank for MemoryBankZero {
     fn read_memory(&self, address: u16) -> u8 {
         match address {
-            0x0 ..= 0x7FFF => self.rom[address as usize],
-            0xA000 ..= 0xBFFF => self.ram[address as usize - 0xA000],
+            0x0 ..= 0x7FFF => self.storage.read_rom(address),
+            0xA000 ..= 0xBFFF => self.storage.read_ram(address),
             _ => unreachable!("Memory bank accessed outside of valid ranges")
         }
     }
 
     fn write_memory(&mut self, address: u16, value: u8) {
         match address {
-            0x4000 ..= 0x7FFF =>  {}
-            0xA000 ..= 0xBFFF => self.ram[address as usize - 0xA000] = value,
+            // there are no registers, so writes to the rom are simply dropped
+            0x0000 ..= 0x7FFF =>  {}
+            0xA000 ..= 0xBFFF => self.storage.write_ram(address, value),
             _ => unreachable!("Memory bank accessed outside of valid ranges")
         }
     }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        self.storage.rom_bank(address)
+    }
+
+    fn export_ram(&self) -> Vec<u8> {
+        self.storage.export_ram()
+    }
+
+    fn import_ram(&mut self, data: &[u8]) {
+        self.storage.import_ram(data);
+    }
 }
 
 
@@ -50,57 +172,74 @@ enum MemoryBankMode {
     RAM,
 }
 
-const ROM_BANK_SIZE: usize = 0x4000;
-const ROM_BANK_COUNT: usize = 125;
+const MULTICART_SIZE: usize = 0x100000;
+// offset and length of the Nintendo logo in the cartridge header
+const LOGO_OFFSET: usize = 0x104;
+const LOGO_SIZE: usize = 0x30;
 
 struct MemoryBankOne {
-    base_rom_bank: [u8; ROM_BANK_SIZE],
-    rom_banks: [u8; ROM_BANK_SIZE * ROM_BANK_COUNT],
-    selected_rom_bank: usize,
-    selected_rom_grouping: usize,
-    ram_banks: [[u8; 0x2000]; 4],
+    storage: Storage,
     ram_enabled: bool,
-    selected_ram_bank: usize,
-    mode: MemoryBankMode
+    bank_one: usize, // 5 bit register, low bits of the rom bank
+    bank_two: usize, // 2 bit register, high bits of the rom bank or the ram bank
+    mode: MemoryBankMode,
+    // MBC1M multicarts only wire 4 bits of the first bank register to the rom
+    multicart: bool,
 }
 
 impl MemoryBankOne {
-    fn new(rom: &[u8]) -> MemoryBankOne {
-        let mut memory_bank = Self {
-            base_rom_bank: [0; ROM_BANK_SIZE],
-            rom_banks: [0; ROM_BANK_SIZE * ROM_BANK_COUNT],
-            selected_rom_bank: 0,
-            selected_rom_grouping: 0,
-            ram_banks: [[0; 0x2000]; 4],
-            ram_enabled: true,
-            selected_ram_bank: 0,
-            mode: MemoryBankMode::ROM
-        };
-
-        for (i, byte) in rom.iter().enumerate() {
-            match i {
-                0x000 ..= 0x3FFF => memory_bank.base_rom_bank[i] = *byte,
-                _ => {
-                    memory_bank.rom_banks[i - ROM_BANK_SIZE] = *byte
-                }
+    fn new(rom: &[u8], cartridge: &Cartridge) -> MemoryBankOne {
+        let storage = Storage::from_header(rom, cartridge);
+        Self {
+            multicart: Self::is_multicart(&storage.rom),
+            storage,
+            ram_enabled: false,
+            bank_one: 1,
+            bank_two: 0,
+            mode: MemoryBankMode::ROM,
+        }
+    }
+
+    /// Multicarts are 1 MB collections of 256 KB games, each starting with its own header,
+    /// so the Nintendo logo shows up again at the start of the second game.
+    fn is_multicart(rom: &[u8]) -> bool {
+        if rom.len() != MULTICART_SIZE {
+            return false;
+        }
+        let logo = &rom[LOGO_OFFSET..LOGO_OFFSET + LOGO_SIZE];
+        let second_game = 0x10 * ROM_BANK_SIZE + LOGO_OFFSET;
+        &rom[second_game..second_game + LOGO_SIZE] == logo
+    }
+
+    /// Recomputes the mapped banks after any register write.
+    fn update_banks(&mut self) {
+        let bank_two_shift = if self.multicart { 4 } else { 5 };
+        let bank_one = if self.multicart { self.bank_one & 0xF } else { self.bank_one };
+        self.storage.map_high_rom(self.bank_two << bank_two_shift | bank_one);
+
+        // in mode 1 the second register also applies to the 0x0000 region and to ram
+        match self.mode {
+            MemoryBankMode::ROM => {
+                self.storage.map_low_rom(0);
+                self.storage.map_ram(0);
+            }
+            MemoryBankMode::RAM => {
+                self.storage.map_low_rom(self.bank_two << bank_two_shift);
+                self.storage.map_ram(self.bank_two);
             }
         }
-        memory_bank
     }
 }
 
 impl MemoryBank for MemoryBankOne {
     fn read_memory(&self, address: u16) -> u8 {
         match address {
-            0x0000 ..= 0x3FFF =>
-                self.base_rom_bank[address as usize],
-            0x4000 ..= 0x7FFF =>
-                self.rom_banks[(self.selected_rom_grouping + self.selected_rom_bank) * ROM_BANK_SIZE + address as usize - ROM_BANK_SIZE],
+            0x0000 ..= 0x7FFF => self.storage.read_rom(address),
             0xA000 ..= 0xBFFF => {
                 if self.ram_enabled {
-                    self.ram_banks[self.selected_ram_bank][address as usize - 0xA000]
+                    self.storage.read_ram(address)
                 } else {
-                    0
+                    0xFF
                 }
             }
             _ => unreachable!("Address {} outisde of memory bank's range", address)
@@ -114,42 +253,614 @@ impl MemoryBank for MemoryBankOne {
                 self.ram_enabled = (value & 0xF) == 0xA
             }
             0x2000 ..= 0x3FFF => {
-                let masked = value & 0b11111;
-                if masked == 0 {
-                    self.selected_rom_bank = 0;
+                // bank 0 can't be selected here, writing 0 selects bank 1 instead. The check
+                // looks at all 5 bits, so banks 0x20, 0x40 and 0x60 map to 0x21, 0x41 and 0x61.
+                let masked = value as usize & 0b11111;
+                self.bank_one = if masked == 0 { 1 } else { masked };
+                self.update_banks();
+            }
+            0x4000 ..= 0x5FFF => {
+                self.bank_two = value as usize & 0b11;
+                self.update_banks();
+            }
+            0x6000 ..= 0x7FFF => {
+                self.mode = if value & 1 == 0 {
+                    MemoryBankMode::ROM
                 } else {
-                    self.selected_rom_bank = masked as usize - 1;
+                    MemoryBankMode::RAM
+                };
+                self.update_banks();
+            }
+            0xA000 ..= 0xBFFF => {
+                if self.ram_enabled {
+                    self.storage.write_ram(address, value);
                 }
             }
+            _ => unreachable!("Address {} outisde of memory bank's range", address)
+
+        }
+    }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        self.storage.rom_bank(address)
+    }
+
+    fn export_ram(&self) -> Vec<u8> {
+        self.storage.export_ram()
+    }
+
+    fn import_ram(&mut self, data: &[u8]) {
+        self.storage.import_ram(data);
+    }
+}
+
+const MBC2_RAM_SIZE: usize = 0x200;
+
+struct MemoryBankTwo {
+    // the 512 half-byte ram is built into the MBC, so the header always declares none
+    storage: Storage,
+    ram_enabled: bool,
+}
+
+impl MemoryBankTwo {
+    fn new(rom: &[u8]) -> MemoryBankTwo {
+        Self {
+            storage: Storage::new(rom, MBC2_RAM_SIZE),
+            ram_enabled: false,
+        }
+    }
+}
+
+impl MemoryBank for MemoryBankTwo {
+    fn read_memory(&self, address: u16) -> u8 {
+        match address {
+            0x0000 ..= 0x7FFF => self.storage.read_rom(address),
+            0xA000 ..= 0xBFFF => {
+                if self.ram_enabled {
+                    // only the low nibble is backed by memory
+                    0xF0 | self.storage.read_ram(address)
+                } else {
+                    0xFF
+                }
+            }
+            _ => unreachable!("Address {} outisde of memory bank's range", address)
+        }
+    }
+
+    fn write_memory(&mut self, address: u16, value: u8) {
+        match address {
+            // bit 8 of the address picks between the ram enable and rom bank registers
+            0x0000 ..= 0x3FFF => {
+                if address & 0x100 == 0 {
+                    self.ram_enabled = (value & 0xF) == 0xA;
+                } else {
+                    let masked = value as usize & 0xF;
+                    self.storage.map_high_rom(if masked == 0 { 1 } else { masked });
+                }
+            }
+            0x4000 ..= 0x7FFF => {}
+            0xA000 ..= 0xBFFF => {
+                if self.ram_enabled {
+                    self.storage.write_ram(address, value & 0xF);
+                }
+            }
+            _ => unreachable!("Address {} outisde of memory bank's range", address)
+        }
+    }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        self.storage.rom_bank(address)
+    }
+
+    fn export_ram(&self) -> Vec<u8> {
+        self.storage.export_ram()
+    }
+
+    fn import_ram(&mut self, data: &[u8]) {
+        self.storage.import_ram(data);
+        for value in self.storage.ram.iter_mut() {
+            *value &= 0xF;
+        }
+    }
+}
+
+// the clock is driven by emulated time, and a second is 2^20 machine cycles
+const CYCLES_PER_SECOND: u32 = 0x100000;
+const RTC_SAVE_SIZE: usize = 48;
+
+struct RealTimeClock {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    days: u16, // 9 bits, the top one lives in the day high register
+    halted: bool,
+    day_carry: bool,
+    cycles: u32,
+    // the game reads a snapshot of the registers taken by the latch sequence
+    latched: [u8; 5],
+    latch_armed: bool,
+}
+
+impl RealTimeClock {
+    fn new() -> RealTimeClock {
+        RealTimeClock {
+            seconds: 0,
+            minutes: 0,
+            hours: 0,
+            days: 0,
+            halted: false,
+            day_carry: false,
+            cycles: 0,
+            latched: [0; 5],
+            latch_armed: false,
+        }
+    }
+
+    fn registers(&self) -> [u8; 5] {
+        [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            (self.days & 0xFF) as u8,
+            (self.day_carry as u8) << 7 | (self.halted as u8) << 6 | (self.days >> 8) as u8,
+        ]
+    }
+
+    /// Latching happens when 0x00 and then 0x01 are written to 0x6000-0x7FFF.
+    fn write_latch(&mut self, value: u8) {
+        if self.latch_armed && value == 0x01 {
+            self.latched = self.registers();
+        }
+        self.latch_armed = value == 0x00;
+    }
+
+    fn read_register(&self, register: u8) -> u8 {
+        self.latched[(register - 0x08) as usize]
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) {
+        match register {
+            0x08 => {
+                self.seconds = value & 0x3F;
+                // writing the seconds restarts the current second
+                self.cycles = 0;
+            }
+            0x09 => self.minutes = value & 0x3F,
+            0x0A => self.hours = value & 0x1F,
+            0x0B => self.days = (self.days & 0x100) | value as u16,
+            0x0C => {
+                self.days = (self.days & 0xFF) | ((value as u16 & 1) << 8);
+                self.halted = value & 0x40 != 0;
+                self.day_carry = value & 0x80 != 0;
+            }
+            _ => unreachable!("Invalid RTC register {}", register)
+        }
+    }
+
+    /// Five little endian words for the live registers, five for the latched ones, then the
+    /// unix time the state was saved at.
+    fn export(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(RTC_SAVE_SIZE);
+        for register in self.registers().iter().chain(self.latched.iter()) {
+            data.extend_from_slice(&(*register as u32).to_le_bytes());
+        }
+        data.extend_from_slice(&Self::now().to_le_bytes());
+        data
+    }
+
+    fn import(&mut self, data: &[u8]) {
+        // older saves store the timestamp in 32 bits
+        if data.len() < RTC_SAVE_SIZE - 4 {
+            return;
+        }
+        let word = |index: usize| data[index * 4];
+        for register in 0..5 {
+            self.write_register(0x08 + register as u8, word(register));
+            self.latched[register] = word(register + 5);
+        }
+        let mut timestamp = [0; 8];
+        import_bytes(&mut timestamp, &data[40..]);
+        let timestamp = u64::from_le_bytes(timestamp);
+
+        // catch up with the time that passed while the emulator wasn't running, unless the
+        // save doesn't say when that was or claims to come from the future
+        let now = Self::now();
+        if !self.halted && timestamp != 0 && timestamp <= now {
+            self.advance(now - timestamp);
+        }
+    }
+
+    fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs())
+    }
+
+    fn tick(&mut self, elapsed: u32) {
+        if self.halted {
+            return;
+        }
+        self.cycles += elapsed;
+        while self.cycles >= CYCLES_PER_SECOND {
+            self.cycles -= CYCLES_PER_SECOND;
+            self.advance_second();
+        }
+    }
+
+    /// Moves the clock forward by any number of seconds at once.
+    fn advance(&mut self, mut seconds: u64) {
+        // out of range values have to wrap at the register's width before carrying works
+        // normally, which only takes a few hours of single steps at worst
+        while seconds > 0 && (self.seconds >= 60 || self.minutes >= 60 || self.hours >= 24) {
+            self.advance_second();
+            seconds -= 1;
+        }
+        let seconds = self.seconds as u64 + seconds;
+        self.seconds = (seconds % 60) as u8;
+        let minutes = self.minutes as u64 + seconds / 60;
+        self.minutes = (minutes % 60) as u8;
+        let hours = self.hours as u64 + minutes / 60;
+        self.hours = (hours % 24) as u8;
+        let days = self.days as u64 + hours / 24;
+        self.days = (days & 0x1FF) as u16;
+        if days > 0x1FF {
+            self.day_carry = true;
+        }
+    }
+
+    /// Counters only carry when they reach their limit, so out of range values written by
+    /// the game just wrap around at the register's width instead.
+    fn advance_second(&mut self) {
+        self.seconds = (self.seconds + 1) & 0x3F;
+        if self.seconds != 60 {
+            return;
+        }
+        self.seconds = 0;
+        self.minutes = (self.minutes + 1) & 0x3F;
+        if self.minutes != 60 {
+            return;
+        }
+        self.minutes = 0;
+        self.hours = (self.hours + 1) & 0x1F;
+        if self.hours != 24 {
+            return;
+        }
+        self.hours = 0;
+        self.days += 1;
+        if self.days > 0x1FF {
+            self.days = 0;
+            self.day_carry = true;
+        }
+    }
+}
+
+struct MemoryBankThree {
+    storage: Storage,
+    ram_and_rtc_enabled: bool,
+    // 0x00-0x03 select a ram bank, 0x08-0x0C an RTC register
+    selected_ram_bank: u8,
+    rtc: Option<RealTimeClock>,
+}
+
+impl MemoryBankThree {
+    fn new(rom: &[u8], cartridge: &Cartridge, has_rtc: bool) -> MemoryBankThree {
+        Self {
+            storage: Storage::from_header(rom, cartridge),
+            ram_and_rtc_enabled: false,
+            selected_ram_bank: 0,
+            rtc: if has_rtc { Some(RealTimeClock::new()) } else { None },
+        }
+    }
+}
+
+impl MemoryBank for MemoryBankThree {
+    fn read_memory(&self, address: u16) -> u8 {
+        match address {
+            0x0000 ..= 0x7FFF => self.storage.read_rom(address),
+            0xA000 ..= 0xBFFF => {
+                if !self.ram_and_rtc_enabled {
+                    return 0xFF;
+                }
+                match (self.selected_ram_bank, self.rtc.as_ref()) {
+                    (0x00 ..= 0x03, _) => self.storage.read_ram(address),
+                    (0x08 ..= 0x0C, Some(rtc)) => rtc.read_register(self.selected_ram_bank),
+                    _ => 0xFF
+                }
+            }
+            _ => unreachable!("Address {} outisde of memory bank's range", address)
+        }
+    }
+
+    fn write_memory(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000 ..= 0x1FFF => {
+                self.ram_and_rtc_enabled = (value & 0xF) == 0xA
+            }
+            0x2000 ..= 0x3FFF => {
+                let masked = value as usize & 0x7F;
+                self.storage.map_high_rom(if masked == 0 { 1 } else { masked });
+            }
             0x4000 ..= 0x5FFF => {
-                let selected = value & 0xC0; // 2 high bits
-                match self.mode {
-                    MemoryBankMode::ROM => self.selected_rom_grouping = selected as usize * 31,
-                    MemoryBankMode::RAM => self.selected_ram_bank = selected as usize
+                self.selected_ram_bank = value;
+                if value <= 0x03 {
+                    self.storage.map_ram(value as usize);
                 }
             }
             0x6000 ..= 0x7FFF => {
-                self.mode = match value {
-                    0 => MemoryBankMode::ROM,
-                    1 => MemoryBankMode::RAM,
-                    _ => panic!("Unsupported mode")
+                if let Some(rtc) = self.rtc.as_mut() {
+                    rtc.write_latch(value);
                 }
             }
+            0xA000 ..= 0xBFFF => {
+                if !self.ram_and_rtc_enabled {
+                    return;
+                }
+                match (self.selected_ram_bank, self.rtc.as_mut()) {
+                    (0x00 ..= 0x03, _) => self.storage.write_ram(address, value),
+                    (0x08 ..= 0x0C, Some(rtc)) => rtc.write_register(self.selected_ram_bank, value),
+                    _ => {}
+                }
+            }
+            _ => unreachable!("Address {} outisde of memory bank's range", address)
+        }
+    }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        self.storage.rom_bank(address)
+    }
+
+    /// The clock state is appended after the ram, in the 48 byte layout most emulators share.
+    fn export_ram(&self) -> Vec<u8> {
+        let mut data = self.storage.export_ram();
+        if let Some(rtc) = self.rtc.as_ref() {
+            data.extend(rtc.export());
+        }
+        data
+    }
+
+    fn import_ram(&mut self, data: &[u8]) {
+        let ram_size = self.storage.ram.len();
+        self.storage.import_ram(data);
+        if let (Some(rtc), Some(rtc_data)) = (self.rtc.as_mut(), data.get(ram_size..)) {
+            rtc.import(rtc_data);
+        }
+    }
+
+    fn tick(&mut self, elapsed: u32) {
+        if let Some(rtc) = self.rtc.as_mut() {
+            rtc.tick(elapsed);
+        }
+    }
+}
+
+// rumble events that are never drained only keep this many of the latest ones
+const MAX_RUMBLE_EVENTS: usize = 256;
+
+struct MemoryBankFive {
+    storage: Storage,
+    ram_enabled: bool,
+    selected_rom_bank: usize, // 9 bits, and unlike the other MBCs bank 0 can be selected
+    // rumble carts wire bit 3 of the ram bank register to the motor instead
+    has_rumble: bool,
+    rumble_on: bool,
+    rumble_events: VecDeque<bool>,
+}
+
+impl MemoryBankFive {
+    fn new(rom: &[u8], cartridge: &Cartridge, has_rumble: bool) -> MemoryBankFive {
+        Self {
+            storage: Storage::from_header(rom, cartridge),
+            ram_enabled: false,
+            selected_rom_bank: 1,
+            has_rumble,
+            rumble_on: false,
+            rumble_events: VecDeque::new(),
+        }
+    }
+}
+
+impl MemoryBank for MemoryBankFive {
+    fn read_memory(&self, address: u16) -> u8 {
+        match address {
+            0x0000 ..= 0x7FFF => self.storage.read_rom(address),
             0xA000 ..= 0xBFFF => {
                 if self.ram_enabled {
-                    self.ram_banks[self.selected_ram_bank][address as usize - 0xA000] = value
+                    self.storage.read_ram(address)
+                } else {
+                    0xFF
                 }
             }
             _ => unreachable!("Address {} outisde of memory bank's range", address)
+        }
+    }
 
+    fn write_memory(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000 ..= 0x1FFF => {
+                self.ram_enabled = (value & 0xF) == 0xA
+            }
+            0x2000 ..= 0x2FFF => {
+                self.selected_rom_bank = (self.selected_rom_bank & 0x100) | value as usize;
+                self.storage.map_high_rom(self.selected_rom_bank);
+            }
+            0x3000 ..= 0x3FFF => {
+                self.selected_rom_bank = (self.selected_rom_bank & 0xFF) | ((value as usize & 1) << 8);
+                self.storage.map_high_rom(self.selected_rom_bank);
+            }
+            0x4000 ..= 0x5FFF => {
+                if self.has_rumble {
+                    self.storage.map_ram(value as usize & 0x7);
+                    let rumble_on = value & 0x8 != 0;
+                    if rumble_on != self.rumble_on {
+                        self.rumble_on = rumble_on;
+                        if self.rumble_events.len() == MAX_RUMBLE_EVENTS {
+                            self.rumble_events.pop_front();
+                        }
+                        self.rumble_events.push_back(rumble_on);
+                    }
+                } else {
+                    self.storage.map_ram(value as usize & 0xF);
+                }
+            }
+            0x6000 ..= 0x7FFF => {}
+            0xA000 ..= 0xBFFF => {
+                if self.ram_enabled {
+                    self.storage.write_ram(address, value);
+                }
+            }
+            _ => unreachable!("Address {} outisde of memory bank's range", address)
         }
     }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        self.storage.rom_bank(address)
+    }
+
+    fn export_ram(&self) -> Vec<u8> {
+        self.storage.export_ram()
+    }
+
+    fn import_ram(&mut self, data: &[u8]) {
+        self.storage.import_ram(data);
+    }
+
+    fn take_rumble_events(&mut self) -> Vec<bool> {
+        std::mem::take(&mut self.rumble_events).into()
+    }
 }
 
-pub fn instantiate_memory_bank(rom: &[u8]) -> Box<dyn MemoryBank> {
-    match rom[0x147] {
-        0x0 => Box::new(MemoryBankZero::new(rom)),
-        0x1 ..= 0x3 => Box::new(MemoryBankOne::new(rom)),
-        _ => panic!("Unsupported memory bank type {}", rom[0x147])
+pub fn instantiate_memory_bank(rom: &[u8], cartridge: &Cartridge) -> Result<Box<dyn MemoryBank>, LoadError> {
+    let memory_bank: Box<dyn MemoryBank> = match cartridge.cartridge_type {
+        0x0 | 0x8 ..= 0x9 => Box::new(MemoryBankZero::new(rom, cartridge)),
+        0x1 ..= 0x3 => Box::new(MemoryBankOne::new(rom, cartridge)),
+        0x5 ..= 0x6 => Box::new(MemoryBankTwo::new(rom)),
+        0xF ..= 0x10 => Box::new(MemoryBankThree::new(rom, cartridge, true)),
+        0x11 ..= 0x13 => Box::new(MemoryBankThree::new(rom, cartridge, false)),
+        0x19 ..= 0x1B => Box::new(MemoryBankFive::new(rom, cartridge, false)),
+        0x1C ..= 0x1E => Box::new(MemoryBankFive::new(rom, cartridge, true)),
+        _ => return Err(LoadError::UnsupportedCartridge {
+            cartridge_type: cartridge.cartridge_type,
+            name: cartridge.cartridge_type_name(),
+        })
+    };
+    Ok(memory_bank)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::test_rom;
+
+    fn memory_bank(rom: &[u8]) -> Box<dyn MemoryBank> {
+        instantiate_memory_bank(rom, &Cartridge::parse(rom).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn undrained_rumble_events_keep_the_latest_ones() {
+        let mut memory_bank = memory_bank(&test_rom(0x1C, 2));
+        for i in 0..MAX_RUMBLE_EVENTS + 1 {
+            memory_bank.write_memory(0x4000, if i & 1 == 0 { 0x08 } else { 0x00 });
+        }
+        let events = memory_bank.take_rumble_events();
+        assert_eq!(events.len(), MAX_RUMBLE_EVENTS);
+        assert_eq!(events.last(), Some(&true));
+        assert!(memory_bank.take_rumble_events().is_empty());
+    }
+
+    #[test]
+    fn rtc_advances_arithmetically() {
+        let mut rtc = RealTimeClock::new();
+        rtc.advance(86400 + 3600 + 60 + 1);
+        assert_eq!(rtc.registers(), [1, 1, 1, 1, 0]);
+        rtc.advance(59 * 60 + 58);
+        assert_eq!(rtc.registers(), [59, 0, 2, 1, 0]);
+    }
+
+    #[test]
+    fn rtc_day_overflow_sets_carry() {
+        let mut rtc = RealTimeClock::new();
+        rtc.days = 0x1FF;
+        rtc.hours = 23;
+        rtc.advance(3600);
+        assert_eq!(rtc.days, 0);
+        assert!(rtc.day_carry);
+    }
+
+    #[test]
+    fn rtc_out_of_range_values_wrap_without_carrying() {
+        let mut rtc = RealTimeClock::new();
+        rtc.seconds = 62;
+        rtc.advance(3);
+        assert_eq!((rtc.seconds, rtc.minutes), (1, 0));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn rtc_import_ignores_missing_and_future_timestamps() {
+        let mut rtc = RealTimeClock::new();
+        let mut data = rtc.export();
+        data[40..].copy_from_slice(&0u64.to_le_bytes());
+        rtc.import(&data);
+        assert_eq!(rtc.registers(), [0; 5]);
+
+        data[40..].copy_from_slice(&(RealTimeClock::now() + 1000).to_le_bytes());
+        rtc.import(&data);
+        assert_eq!(rtc.registers(), [0; 5]);
+
+        data[40..].copy_from_slice(&(RealTimeClock::now() - 90).to_le_bytes());
+        rtc.import(&data);
+        // the clock may tick over between the export and the import
+        assert_eq!(rtc.minutes, 1);
+        assert!((30..=31).contains(&rtc.seconds));
+    }
+
+    #[test]
+    fn mbc1_bank_zero_in_the_first_register_selects_the_next_bank() {
+        let mut memory_bank = memory_bank(&test_rom(0x01, 128));
+        for (bank_two, expected) in [(0, 0x01), (1, 0x21), (2, 0x41), (3, 0x61)].iter() {
+            memory_bank.write_memory(0x4000, *bank_two);
+            memory_bank.write_memory(0x2000, 0x00);
+            assert_eq!(memory_bank.read_memory(0x4000), *expected);
+        }
+    }
+
+    #[test]
+    fn mbc1_mode_one_maps_the_second_register_at_0000() {
+        let mut memory_bank = memory_bank(&test_rom(0x01, 128));
+        memory_bank.write_memory(0x4000, 0x02);
+        assert_eq!(memory_bank.read_memory(0x0000), 0x00);
+        memory_bank.write_memory(0x6000, 0x01);
+        assert_eq!(memory_bank.read_memory(0x0000), 0x40);
+        assert_eq!(memory_bank.rom_bank(0x0000), 0x40);
+        memory_bank.write_memory(0x6000, 0x00);
+        assert_eq!(memory_bank.read_memory(0x0000), 0x00);
+    }
+
+    #[test]
+    fn mbc1_masks_banks_to_the_rom_size() {
+        let mut memory_bank = memory_bank(&test_rom(0x01, 4));
+        memory_bank.write_memory(0x2000, 0x05);
+        assert_eq!(memory_bank.read_memory(0x4000), 0x01);
+        memory_bank.write_memory(0x2000, 0x1F);
+        assert_eq!(memory_bank.read_memory(0x4000), 0x03);
+    }
+
+    #[test]
+    fn mbc1_multicart_is_detected_by_the_second_logo() {
+        let mut rom = test_rom(0x01, 64);
+        for (i, byte) in rom[LOGO_OFFSET..LOGO_OFFSET + LOGO_SIZE].iter_mut().enumerate() {
+            *byte = i as u8 + 1;
+        }
+        // without the second game's logo it's a plain 1 MB MBC1 cart
+        let mut plain = memory_bank(&rom);
+        plain.write_memory(0x4000, 0x01);
+        plain.write_memory(0x2000, 0x02);
+        assert_eq!(plain.read_memory(0x4000), 0x22);
+
+        let second_game = 0x10 * ROM_BANK_SIZE + LOGO_OFFSET;
+        rom.copy_within(LOGO_OFFSET..LOGO_OFFSET + LOGO_SIZE, second_game);
+        let mut multicart = memory_bank(&rom);
+        multicart.write_memory(0x4000, 0x01);
+        multicart.write_memory(0x2000, 0x12);
+        assert_eq!(multicart.read_memory(0x4000), 0x12);
+        multicart.write_memory(0x6000, 0x01);
+        assert_eq!(multicart.read_memory(0x0000), 0x10);
+    }
+}
@@ -1,66 +1,146 @@
 use crate::interupt::Interrupt;
 use crate::timer::Timer;
+use crate::ppu::{PPU, SCREEN_WIDTH, SCREEN_HEIGHT};
+use crate::dma::DMA;
+use crate::joypad::{Joypad, Button};
+use crate::apu::APU;
+use crate::audio::AudioSink;
+use crate::serial::{Serial, SerialPeer};
 use crate::memory_bank::{MemoryBank, instantiate_memory_bank};
+use crate::cartridge::Cartridge;
+use crate::error::LoadError;
 
 const WORKING_RAM_SIZE: usize = 0x2000;
+const HIGH_RAM_SIZE: usize = 0x7F;
+const DMG_BOOT_ROM_SIZE: usize = 0x100;
+const CGB_BOOT_ROM_SIZE: usize = 0x900;
 
 pub struct MMU {
+    cartridge: Cartridge,
+    // overlays the start of the cartridge until a write to 0xFF50
+    boot_rom: Option<Box<[u8]>>,
     memory_bank: Box<dyn MemoryBank>,
     working_ram: [u8; WORKING_RAM_SIZE],
-    memory: [u8; 0x10000],
+    high_ram: [u8; HIGH_RAM_SIZE],
     timer: Timer,
+    ppu: PPU,
+    dma: DMA,
+    joypad: Joypad,
+    apu: APU,
+    serial: Serial,
     interrupt_e: u8,
     interrupt_f: u8,
+    // KEY1 only exists when running a CGB cartridge
+    cgb_mode: bool,
+    double_speed: bool,
+    speed_switch_armed: bool,
+    // the odd machine cycle left over when halving for the normal speed units
+    half_cycle: bool,
 }
 
 impl MMU {
-    pub fn new(rom: &[u8]) -> Self {
-        MMU {
-            memory_bank: instantiate_memory_bank(rom),
+    pub fn new(rom: &[u8]) -> Result<Self, LoadError> {
+        let cartridge = Cartridge::parse(rom).ok_or(LoadError::MissingHeader)?;
+        Ok(MMU {
+            memory_bank: instantiate_memory_bank(rom, &cartridge)?,
+            cgb_mode: cartridge.supports_cgb(),
+            boot_rom: None,
+            cartridge,
             working_ram: [0; WORKING_RAM_SIZE],
-            memory: [0; 0x10000],
+            high_ram: [0; HIGH_RAM_SIZE],
             interrupt_e: 0,
             interrupt_f: 0,
-            timer: Timer::new()
-       }
+            timer: Timer::new(),
+            ppu: PPU::new(),
+            dma: DMA::new(),
+            joypad: Joypad::new(),
+            apu: APU::new(),
+            serial: Serial::new(),
+            double_speed: false,
+            speed_switch_armed: false,
+            half_cycle: false,
+        })
     }
 
     pub fn read_memory(&self, address: u16) -> u8 {
+        // while DMA owns the bus the CPU can only reach the 0xFF00 page
+        if self.dma.is_transferring() && address < 0xFF00 {
+            return 0xFF;
+        }
+        self.read_bus(address)
+    }
+
+    fn read_bus(&self, address: u16) -> u8 {
+        if let Some(value) = self.read_boot_rom(address) {
+            return value;
+        }
         match address {
-            0x0000 ..= 0x7FFE | 0xA000 ..= 0xBFFE => self.memory_bank.read_memory(address),
-            0xC000 ..= 0xDFFE => self.working_ram[(address as usize) - 0xC000],
+            0x0000 ..= 0x7FFF | 0xA000 ..= 0xBFFF => self.memory_bank.read_memory(address),
+            0x8000 ..= 0x9FFF | 0xFE00 ..= 0xFE9F => self.ppu.read_byte(address),
+            0xC000 ..= 0xDFFF => self.working_ram[(address as usize) - 0xC000],
             // shadow copy of working ram
-            0xE000 ..= 0xFDFE => self.working_ram[(address as usize) - 0xE000],
-
+            0xE000 ..= 0xFDFF => self.working_ram[(address as usize) - 0xE000],
+            // unusable region after OAM
+            0xFEA0 ..= 0xFEFF => 0x00,
+            0xFF00 => self.joypad.read_byte(),
+            0xFF01 ..= 0xFF02 => self.serial.read_byte(address),
             0xFF04 ..= 0xFF07 => self.timer.read_byte(address),
+            0xFF10 ..= 0xFF3F => self.apu.read_byte(address),
+            0xFF40 ..= 0xFF45 | 0xFF47 ..= 0xFF4B => self.ppu.read_byte(address),
+            0xFF46 => self.dma.read_byte(),
+            0xFF4D if self.cgb_mode =>
+                0x7E | (self.double_speed as u8) << 7 | self.speed_switch_armed as u8,
             0xFF0F =>
                 self.interrupt_f,
+            0xFF80 ..= 0xFFFE => self.high_ram[(address as usize) - 0xFF80],
             0xFFFF => self.interrupt_e,
-            _ => self.memory[address as usize]
+            // unmapped I/O registers
+            _ => 0xFF
         }
     }
 
     pub fn write_memory(&mut self, address: u16, value: u8) {
+        if self.dma.is_transferring() && address < 0xFF00 {
+            return;
+        }
         match address {
             0x0000 ..= 0x7FFF | 0xA000 ..= 0xBFFF => self.memory_bank.write_memory(address, value),
+            0x8000 ..= 0x9FFF | 0xFE00 ..= 0xFE9F => self.ppu.write_byte(address, value),
             0xC000 ..= 0xDFFF => self.working_ram[(address as usize) - 0xC000] = value,
             // shadow copy of working ram
             0xE000 ..= 0xFDFF => self.working_ram[(address as usize) - 0xE000] = value,
-            0xFF01 => print!("{}", value as char),
-            0xFF04 ..= 0xFF07 =>
-                self.timer.write_byte(address, value),
+            0xFF00 => self.write_joypad(value),
+            0xFF01 ..= 0xFF02 => self.serial.write_byte(address, value),
+            0xFF04 ..= 0xFF07 => {
+                if self.timer.write_byte(address, value) {
+                    self.request_interrupt(Interrupt::TimeOverflow);
+                }
+                self.apu.update_divider(self.timer.divider());
+            }
+            0xFF10 ..= 0xFF3F => self.apu.write_byte(address, value),
+            0xFF40 ..= 0xFF45 | 0xFF47 ..= 0xFF4B => self.ppu.write_byte(address, value),
+            0xFF46 => self.dma.write_byte(value),
+            0xFF4D if self.cgb_mode => self.speed_switch_armed = value & 1 != 0,
+            // the boot rom unmaps itself as its last act, and can't be mapped back in
+            0xFF50 if value & 1 != 0 => self.boot_rom = None,
             0xFF0F => self.interrupt_f = value,
+            0xFF80 ..= 0xFFFE => self.high_ram[(address as usize) - 0xFF80] = value,
             0xFFFF => self.interrupt_e = value,
-            _ => self.memory[address as usize] = value
+            // unmapped I/O registers
+            _ => {}
         }
     }
 
-    pub fn is_interrupt_waiting(&self) -> bool {
-        match self.get_first_active_interrupt() {
-            Some(_) => true,
-            None => false
+    fn write_joypad(&mut self, value: u8) {
+        // selecting a row with a held button pulls an input line low
+        if self.joypad.write_byte(value) {
+            self.request_interrupt(Interrupt::JoypadPress);
         }
     }
+
+    pub fn is_interrupt_waiting(&self) -> bool {
+        self.get_first_active_interrupt().is_some()
+    }
     pub fn get_first_active_interrupt(&self) -> Option<Interrupt> {
         Interrupt::first_from(self.interrupt_e & self.interrupt_f)
     }
@@ -70,17 +150,188 @@ impl MMU {
         self.interrupt_f &= !(1 << index);
     }
 
-    pub fn stub(&mut self) {
-        self.memory[0xFF44] = 0x90;
+    /// Maps a boot rom over the cartridge and puts the hardware in its power on state,
+    /// leaving it to the boot rom to set up what is normally there at 0x0100.
+    pub fn load_boot_rom(&mut self, boot_rom: &[u8]) -> Result<(), LoadError> {
+        if boot_rom.len() != DMG_BOOT_ROM_SIZE && boot_rom.len() != CGB_BOOT_ROM_SIZE {
+            return Err(LoadError::InvalidBootRom { size: boot_rom.len() });
+        }
+        self.boot_rom = Some(boot_rom.into());
+        self.write_memory(0xFF04, 0);
+        self.write_memory(0xFF26, 0);
+        self.write_memory(0xFF40, 0);
+        self.write_memory(0xFF47, 0);
+        Ok(())
+    }
+
+    fn read_boot_rom(&self, address: u16) -> Option<u8> {
+        let boot_rom = self.boot_rom.as_ref()?;
+        match address {
+            0x0000 ..= 0x00FF => Some(boot_rom[address as usize]),
+            // the CGB boot rom continues after the cartridge header
+            0x0200 ..= 0x08FF => boot_rom.get(address as usize).copied(),
+            _ => None
+        }
+    }
+
+    /// Performs the speed switch armed through KEY1, returning false if none was armed.
+    pub fn switch_speed(&mut self) -> bool {
+        if !self.speed_switch_armed {
+            return false;
+        }
+        self.speed_switch_armed = false;
+        self.double_speed = !self.double_speed;
+        self.apu.set_double_speed(self.double_speed);
+        true
+    }
+
+    pub fn reset_divider(&mut self) {
+        self.write_memory(0xFF04, 0);
+    }
+
+    pub fn is_joypad_line_low(&self) -> bool {
+        self.joypad.is_any_line_low()
+    }
+
+    /// The cartridge rom bank currently mapped at the given address, or None if the address
+    /// isn't cartridge rom.
+    pub fn rom_bank(&self, address: u16) -> Option<usize> {
+        if address >= 0x8000 || self.read_boot_rom(address).is_some() {
+            return None;
+        }
+        Some(self.memory_bank.rom_bank(address))
+    }
+
+    pub fn press(&mut self, button: Button) {
+        if self.joypad.press(button) {
+            self.request_interrupt(Interrupt::JoypadPress);
+        }
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.joypad.release(button);
+    }
+
+    pub fn set_serial_peer(&mut self, peer: Box<dyn SerialPeer>) {
+        self.serial.set_peer(peer);
+    }
+
+    pub fn take_serial_output(&mut self) -> Vec<u8> {
+        self.serial.take_output()
     }
 
+    pub fn take_rumble_events(&mut self) -> Vec<bool> {
+        self.memory_bank.take_rumble_events()
+    }
+
+    pub fn cartridge(&self) -> &Cartridge {
+        &self.cartridge
+    }
+
+    pub fn export_ram(&self) -> Vec<u8> {
+        self.memory_bank.export_ram()
+    }
+
+    pub fn import_ram(&mut self, data: &[u8]) {
+        self.memory_bank.import_ram(data);
+    }
+
+    pub fn set_audio_sink(&mut self, sink: Box<dyn AudioSink>) {
+        self.apu.set_audio_sink(sink);
+    }
+
+    pub fn audio_error(&self) -> Option<&std::io::Error> {
+        self.apu.audio_error()
+    }
+
+    pub fn framebuffer(&self) -> &[u8; SCREEN_WIDTH * SCREEN_HEIGHT] {
+        self.ppu.framebuffer()
+    }
+
+    /// Advances the hardware by `elapsed` CPU machine cycles. The timer, serial port and DMA
+    /// share the CPU clock, while the PPU, APU and cartridge always run at normal speed.
     pub fn tick(&mut self, elapsed: u32) {
         if self.timer.tick(elapsed) {
-            self.set_timer_interrupt();
+            self.request_interrupt(Interrupt::TimeOverflow);
+        }
+        if self.serial.tick(elapsed) {
+            self.request_interrupt(Interrupt::SerialLink);
+        }
+        let normal_speed_elapsed = self.normal_speed_cycles(elapsed);
+        self.interrupt_f |= self.ppu.tick(normal_speed_elapsed);
+        self.memory_bank.tick(normal_speed_elapsed);
+        self.apu.tick(normal_speed_elapsed);
+        self.apu.update_divider(self.timer.divider());
+        for _ in 0..elapsed {
+            if let Some((source, destination)) = self.dma.tick() {
+                let value = self.read_bus(source);
+                self.ppu.write_byte(destination, value);
+            }
         }
     }
-    fn set_timer_interrupt(&mut self) {
-        self.interrupt_f |= 0b1 << 2;
+
+    fn normal_speed_cycles(&mut self, elapsed: u32) -> u32 {
+        if !self.double_speed {
+            return elapsed;
+        }
+        let total = elapsed + self.half_cycle as u32;
+        self.half_cycle = total & 1 != 0;
+        total / 2
+    }
+
+    fn request_interrupt(&mut self, interrupt: Interrupt) {
+        self.interrupt_f |= 1 << interrupt.get_index();
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::test_rom;
+    use crate::cpu::CPU;
+
+    #[test]
+    fn boot_rom_unmaps_itself_for_good() {
+        let mut rom = test_rom(0x00, 2);
+        rom[..0x100].iter_mut().for_each(|byte| *byte = 0xFF);
+        let mut boot_rom = vec![0; DMG_BOOT_ROM_SIZE];
+        // ld a, 1; ldh [$FF50], a
+        boot_rom[0xFC..].copy_from_slice(&[0x3E, 0x01, 0xE0, 0x50]);
+
+        let mut cpu = CPU::with_boot_rom(&rom, &boot_rom).unwrap();
+        assert_eq!(cpu.mmu.read_memory(0x0000), 0x00);
+        assert_eq!(cpu.mmu.read_memory(0x00FC), 0x3E);
+        assert_eq!(cpu.mmu.read_memory(0x0101), 0xC3);
+        assert_eq!(cpu.mmu.rom_bank(0x0000), None);
+
+        // the nops run into the unmap at the end of the boot rom
+        for _ in 0..0x100 {
+            cpu.tick().unwrap();
+        }
+        assert_eq!(cpu.mmu.read_memory(0x0000), 0xFF);
+        assert_eq!(cpu.mmu.read_memory(0x00FC), 0xFF);
+        assert_eq!(cpu.mmu.rom_bank(0x0000), Some(0));
+
+        cpu.mmu.write_memory(0xFF50, 0x00);
+        cpu.mmu.write_memory(0xFF50, 0x01);
+        assert_eq!(cpu.mmu.read_memory(0x0000), 0xFF);
+    }
+
+    #[test]
+    fn cgb_boot_rom_leaves_the_header_visible() {
+        let mut mmu = MMU::new(&test_rom(0x00, 2)).unwrap();
+        mmu.load_boot_rom(&[0xAA; CGB_BOOT_ROM_SIZE]).unwrap();
+        for address in [0x0000, 0x00FF, 0x0200, 0x08FF].iter() {
+            assert_eq!(mmu.read_memory(*address), 0xAA, "{:04X}", address);
+        }
+        for address in [0x0100, 0x0150, 0x01FF, 0x0900].iter() {
+            assert_ne!(mmu.read_memory(*address), 0xAA, "{:04X}", address);
+        }
+    }
+
+    #[test]
+    fn rejects_boot_roms_of_other_sizes() {
+        let mut mmu = MMU::new(&test_rom(0x00, 2)).unwrap();
+        assert!(matches!(mmu.load_boot_rom(&[0; 0x200]), Err(LoadError::InvalidBootRom { size: 0x200 })));
+    }
+}
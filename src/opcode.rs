@@ -1,4 +1,4 @@
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 #[allow(non_camel_case_types)]
 pub enum Opcode {
     NOP = 0x00,
@@ -505,8 +505,9 @@ pub enum Opcode {
 }
 
 impl Opcode {
-    pub fn from(byte: u8) -> Self {
-        match byte {
+    /// Returns None for the bytes that don't decode to any instruction.
+    pub fn from(byte: u8) -> Option<Self> {
+        let opcode = match byte {
             0x00 => Opcode::NOP,
             0x01 => Opcode::LD_BC_d16,
             0x02 => Opcode::LD_rBC_A,
@@ -752,8 +753,9 @@ impl Opcode {
             0xFB => Opcode::EI,
             0xFE => Opcode::CP_d8,
             0xFF => Opcode::RST_38H,
-            _ => panic!("Unexpected opcode!")
-        }
+            _ => return None
+        };
+        Some(opcode)
     }
 
     pub fn from_extended(byte: u8) -> Opcode {
@@ -1523,4 +1525,642 @@ impl Opcode {
         }
     }
 
-}
\ No newline at end of file
+    /// Whether this is one of the opcodes following a 0xCB prefix.
+    pub fn is_extended(&self) -> bool {
+        *self as u16 > 0xFF
+    }
+
+    pub fn info(&self) -> &'static OpcodeInfo {
+        if self.is_extended() {
+            &EXTENDED_OPCODE_INFO[self.as_byte() as usize]
+        } else {
+            // only the illegal bytes are missing, and those never decode to an Opcode
+            OPCODE_INFO[self.as_byte() as usize].as_ref().unwrap()
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FlagEffect {
+    Unaffected,
+    Reset,
+    Set,
+    /// Depends on the result.
+    Modified,
+}
+
+/// Static facts about an instruction, so cycle counts and operand sizes have a single
+/// source for debuggers, disassemblers and timing checks.
+#[derive(Debug)]
+pub struct OpcodeInfo {
+    /// The assembly, with d8/d16 for immediates, a8/a16 for addresses and r8 for signed
+    /// offsets standing in for the operand bytes.
+    pub mnemonic: &'static str,
+    /// The length in bytes, including the 0xCB prefix for extended opcodes.
+    pub length: u8,
+    /// Machine cycles, for conditional branches when the branch isn't taken.
+    pub cycles: u8,
+    /// Machine cycles when a conditional branch is taken.
+    pub branch_cycles: Option<u8>,
+    /// The effect on the Z, N, H and C flags, in that order.
+    pub flags: [FlagEffect; 4],
+    /// The flags the instruction depends on, as a mask in the layout of the F register.
+    pub flags_read: u8,
+}
+
+const FLAG_NAMES: &[u8; 4] = b"ZNHC";
+
+/// Builds an entry from flags in the usual `Z0H-` notation, where a letter means the flag
+/// depends on the result, and the names of the flags the instruction reads.
+const fn op(mnemonic: &'static str, length: u8, cycles: u8, flags: &'static str, flags_read: &'static str) -> OpcodeInfo {
+    let notation = flags.as_bytes();
+    let mut effects = [FlagEffect::Unaffected; 4];
+    let mut i = 0;
+    while i < 4 {
+        effects[i] = match notation[i] {
+            b'-' => FlagEffect::Unaffected,
+            b'0' => FlagEffect::Reset,
+            b'1' => FlagEffect::Set,
+            _ => FlagEffect::Modified,
+        };
+        i += 1;
+    }
+
+    let read = flags_read.as_bytes();
+    let mut mask = 0;
+    let mut i = 0;
+    while i < read.len() {
+        let mut bit = 0;
+        while FLAG_NAMES[bit] != read[i] {
+            bit += 1;
+        }
+        mask |= 0x80 >> bit;
+        i += 1;
+    }
+
+    OpcodeInfo {
+        mnemonic,
+        length,
+        cycles,
+        branch_cycles: None,
+        flags: effects,
+        flags_read: mask,
+    }
+}
+
+/// A conditional branch, which never changes the flags.
+const fn branch(mnemonic: &'static str, length: u8, cycles: u8, branch_cycles: u8, flags_read: &'static str) -> OpcodeInfo {
+    let mut info = op(mnemonic, length, cycles, "----", flags_read);
+    info.branch_cycles = Some(branch_cycles);
+    info
+}
+
+static OPCODE_INFO: [Option<OpcodeInfo>; 0x100] = [
+    // 0x00
+    Some(op("NOP", 1, 1, "----", "")),
+    Some(op("LD BC,d16", 3, 3, "----", "")),
+    Some(op("LD (BC),A", 1, 2, "----", "")),
+    Some(op("INC BC", 1, 2, "----", "")),
+    Some(op("INC B", 1, 1, "Z0H-", "")),
+    Some(op("DEC B", 1, 1, "Z1H-", "")),
+    Some(op("LD B,d8", 2, 2, "----", "")),
+    Some(op("RLCA", 1, 1, "000C", "")),
+    Some(op("LD (a16),SP", 3, 5, "----", "")),
+    Some(op("ADD HL,BC", 1, 2, "-0HC", "")),
+    Some(op("LD A,(BC)", 1, 2, "----", "")),
+    Some(op("DEC BC", 1, 2, "----", "")),
+    Some(op("INC C", 1, 1, "Z0H-", "")),
+    Some(op("DEC C", 1, 1, "Z1H-", "")),
+    Some(op("LD C,d8", 2, 2, "----", "")),
+    Some(op("RRCA", 1, 1, "000C", "")),
+    // 0x10
+    Some(op("STOP", 2, 2, "----", "")),
+    Some(op("LD DE,d16", 3, 3, "----", "")),
+    Some(op("LD (DE),A", 1, 2, "----", "")),
+    Some(op("INC DE", 1, 2, "----", "")),
+    Some(op("INC D", 1, 1, "Z0H-", "")),
+    Some(op("DEC D", 1, 1, "Z1H-", "")),
+    Some(op("LD D,d8", 2, 2, "----", "")),
+    Some(op("RLA", 1, 1, "000C", "C")),
+    Some(op("JR r8", 2, 3, "----", "")),
+    Some(op("ADD HL,DE", 1, 2, "-0HC", "")),
+    Some(op("LD A,(DE)", 1, 2, "----", "")),
+    Some(op("DEC DE", 1, 2, "----", "")),
+    Some(op("INC E", 1, 1, "Z0H-", "")),
+    Some(op("DEC E", 1, 1, "Z1H-", "")),
+    Some(op("LD E,d8", 2, 2, "----", "")),
+    Some(op("RRA", 1, 1, "000C", "C")),
+    // 0x20
+    Some(branch("JR NZ,r8", 2, 2, 3, "Z")),
+    Some(op("LD HL,d16", 3, 3, "----", "")),
+    Some(op("LD (HL+),A", 1, 2, "----", "")),
+    Some(op("INC HL", 1, 2, "----", "")),
+    Some(op("INC H", 1, 1, "Z0H-", "")),
+    Some(op("DEC H", 1, 1, "Z1H-", "")),
+    Some(op("LD H,d8", 2, 2, "----", "")),
+    Some(op("DAA", 1, 1, "Z-0C", "NHC")),
+    Some(branch("JR Z,r8", 2, 2, 3, "Z")),
+    Some(op("ADD HL,HL", 1, 2, "-0HC", "")),
+    Some(op("LD A,(HL+)", 1, 2, "----", "")),
+    Some(op("DEC HL", 1, 2, "----", "")),
+    Some(op("INC L", 1, 1, "Z0H-", "")),
+    Some(op("DEC L", 1, 1, "Z1H-", "")),
+    Some(op("LD L,d8", 2, 2, "----", "")),
+    Some(op("CPL", 1, 1, "-11-", "")),
+    // 0x30
+    Some(branch("JR NC,r8", 2, 2, 3, "C")),
+    Some(op("LD SP,d16", 3, 3, "----", "")),
+    Some(op("LD (HL-),A", 1, 2, "----", "")),
+    Some(op("INC SP", 1, 2, "----", "")),
+    Some(op("INC (HL)", 1, 3, "Z0H-", "")),
+    Some(op("DEC (HL)", 1, 3, "Z1H-", "")),
+    Some(op("LD (HL),d8", 2, 3, "----", "")),
+    Some(op("SCF", 1, 1, "-001", "")),
+    Some(branch("JR C,r8", 2, 2, 3, "C")),
+    Some(op("ADD HL,SP", 1, 2, "-0HC", "")),
+    Some(op("LD A,(HL-)", 1, 2, "----", "")),
+    Some(op("DEC SP", 1, 2, "----", "")),
+    Some(op("INC A", 1, 1, "Z0H-", "")),
+    Some(op("DEC A", 1, 1, "Z1H-", "")),
+    Some(op("LD A,d8", 2, 2, "----", "")),
+    Some(op("CCF", 1, 1, "-00C", "C")),
+    // 0x40
+    Some(op("LD B,B", 1, 1, "----", "")),
+    Some(op("LD B,C", 1, 1, "----", "")),
+    Some(op("LD B,D", 1, 1, "----", "")),
+    Some(op("LD B,E", 1, 1, "----", "")),
+    Some(op("LD B,H", 1, 1, "----", "")),
+    Some(op("LD B,L", 1, 1, "----", "")),
+    Some(op("LD B,(HL)", 1, 2, "----", "")),
+    Some(op("LD B,A", 1, 1, "----", "")),
+    Some(op("LD C,B", 1, 1, "----", "")),
+    Some(op("LD C,C", 1, 1, "----", "")),
+    Some(op("LD C,D", 1, 1, "----", "")),
+    Some(op("LD C,E", 1, 1, "----", "")),
+    Some(op("LD C,H", 1, 1, "----", "")),
+    Some(op("LD C,L", 1, 1, "----", "")),
+    Some(op("LD C,(HL)", 1, 2, "----", "")),
+    Some(op("LD C,A", 1, 1, "----", "")),
+    // 0x50
+    Some(op("LD D,B", 1, 1, "----", "")),
+    Some(op("LD D,C", 1, 1, "----", "")),
+    Some(op("LD D,D", 1, 1, "----", "")),
+    Some(op("LD D,E", 1, 1, "----", "")),
+    Some(op("LD D,H", 1, 1, "----", "")),
+    Some(op("LD D,L", 1, 1, "----", "")),
+    Some(op("LD D,(HL)", 1, 2, "----", "")),
+    Some(op("LD D,A", 1, 1, "----", "")),
+    Some(op("LD E,B", 1, 1, "----", "")),
+    Some(op("LD E,C", 1, 1, "----", "")),
+    Some(op("LD E,D", 1, 1, "----", "")),
+    Some(op("LD E,E", 1, 1, "----", "")),
+    Some(op("LD E,H", 1, 1, "----", "")),
+    Some(op("LD E,L", 1, 1, "----", "")),
+    Some(op("LD E,(HL)", 1, 2, "----", "")),
+    Some(op("LD E,A", 1, 1, "----", "")),
+    // 0x60
+    Some(op("LD H,B", 1, 1, "----", "")),
+    Some(op("LD H,C", 1, 1, "----", "")),
+    Some(op("LD H,D", 1, 1, "----", "")),
+    Some(op("LD H,E", 1, 1, "----", "")),
+    Some(op("LD H,H", 1, 1, "----", "")),
+    Some(op("LD H,L", 1, 1, "----", "")),
+    Some(op("LD H,(HL)", 1, 2, "----", "")),
+    Some(op("LD H,A", 1, 1, "----", "")),
+    Some(op("LD L,B", 1, 1, "----", "")),
+    Some(op("LD L,C", 1, 1, "----", "")),
+    Some(op("LD L,D", 1, 1, "----", "")),
+    Some(op("LD L,E", 1, 1, "----", "")),
+    Some(op("LD L,H", 1, 1, "----", "")),
+    Some(op("LD L,L", 1, 1, "----", "")),
+    Some(op("LD L,(HL)", 1, 2, "----", "")),
+    Some(op("LD L,A", 1, 1, "----", "")),
+    // 0x70
+    Some(op("LD (HL),B", 1, 2, "----", "")),
+    Some(op("LD (HL),C", 1, 2, "----", "")),
+    Some(op("LD (HL),D", 1, 2, "----", "")),
+    Some(op("LD (HL),E", 1, 2, "----", "")),
+    Some(op("LD (HL),H", 1, 2, "----", "")),
+    Some(op("LD (HL),L", 1, 2, "----", "")),
+    Some(op("HALT", 1, 1, "----", "")),
+    Some(op("LD (HL),A", 1, 2, "----", "")),
+    Some(op("LD A,B", 1, 1, "----", "")),
+    Some(op("LD A,C", 1, 1, "----", "")),
+    Some(op("LD A,D", 1, 1, "----", "")),
+    Some(op("LD A,E", 1, 1, "----", "")),
+    Some(op("LD A,H", 1, 1, "----", "")),
+    Some(op("LD A,L", 1, 1, "----", "")),
+    Some(op("LD A,(HL)", 1, 2, "----", "")),
+    Some(op("LD A,A", 1, 1, "----", "")),
+    // 0x80
+    Some(op("ADD A,B", 1, 1, "Z0HC", "")),
+    Some(op("ADD A,C", 1, 1, "Z0HC", "")),
+    Some(op("ADD A,D", 1, 1, "Z0HC", "")),
+    Some(op("ADD A,E", 1, 1, "Z0HC", "")),
+    Some(op("ADD A,H", 1, 1, "Z0HC", "")),
+    Some(op("ADD A,L", 1, 1, "Z0HC", "")),
+    Some(op("ADD A,(HL)", 1, 2, "Z0HC", "")),
+    Some(op("ADD A,A", 1, 1, "Z0HC", "")),
+    Some(op("ADC A,B", 1, 1, "Z0HC", "C")),
+    Some(op("ADC A,C", 1, 1, "Z0HC", "C")),
+    Some(op("ADC A,D", 1, 1, "Z0HC", "C")),
+    Some(op("ADC A,E", 1, 1, "Z0HC", "C")),
+    Some(op("ADC A,H", 1, 1, "Z0HC", "C")),
+    Some(op("ADC A,L", 1, 1, "Z0HC", "C")),
+    Some(op("ADC A,(HL)", 1, 2, "Z0HC", "C")),
+    Some(op("ADC A,A", 1, 1, "Z0HC", "C")),
+    // 0x90
+    Some(op("SUB B", 1, 1, "Z1HC", "")),
+    Some(op("SUB C", 1, 1, "Z1HC", "")),
+    Some(op("SUB D", 1, 1, "Z1HC", "")),
+    Some(op("SUB E", 1, 1, "Z1HC", "")),
+    Some(op("SUB H", 1, 1, "Z1HC", "")),
+    Some(op("SUB L", 1, 1, "Z1HC", "")),
+    Some(op("SUB (HL)", 1, 2, "Z1HC", "")),
+    Some(op("SUB A", 1, 1, "Z1HC", "")),
+    Some(op("SBC A,B", 1, 1, "Z1HC", "C")),
+    Some(op("SBC A,C", 1, 1, "Z1HC", "C")),
+    Some(op("SBC A,D", 1, 1, "Z1HC", "C")),
+    Some(op("SBC A,E", 1, 1, "Z1HC", "C")),
+    Some(op("SBC A,H", 1, 1, "Z1HC", "C")),
+    Some(op("SBC A,L", 1, 1, "Z1HC", "C")),
+    Some(op("SBC A,(HL)", 1, 2, "Z1HC", "C")),
+    Some(op("SBC A,A", 1, 1, "Z1HC", "C")),
+    // 0xA0
+    Some(op("AND B", 1, 1, "Z010", "")),
+    Some(op("AND C", 1, 1, "Z010", "")),
+    Some(op("AND D", 1, 1, "Z010", "")),
+    Some(op("AND E", 1, 1, "Z010", "")),
+    Some(op("AND H", 1, 1, "Z010", "")),
+    Some(op("AND L", 1, 1, "Z010", "")),
+    Some(op("AND (HL)", 1, 2, "Z010", "")),
+    Some(op("AND A", 1, 1, "Z010", "")),
+    Some(op("XOR B", 1, 1, "Z000", "")),
+    Some(op("XOR C", 1, 1, "Z000", "")),
+    Some(op("XOR D", 1, 1, "Z000", "")),
+    Some(op("XOR E", 1, 1, "Z000", "")),
+    Some(op("XOR H", 1, 1, "Z000", "")),
+    Some(op("XOR L", 1, 1, "Z000", "")),
+    Some(op("XOR (HL)", 1, 2, "Z000", "")),
+    Some(op("XOR A", 1, 1, "Z000", "")),
+    // 0xB0
+    Some(op("OR B", 1, 1, "Z000", "")),
+    Some(op("OR C", 1, 1, "Z000", "")),
+    Some(op("OR D", 1, 1, "Z000", "")),
+    Some(op("OR E", 1, 1, "Z000", "")),
+    Some(op("OR H", 1, 1, "Z000", "")),
+    Some(op("OR L", 1, 1, "Z000", "")),
+    Some(op("OR (HL)", 1, 2, "Z000", "")),
+    Some(op("OR A", 1, 1, "Z000", "")),
+    Some(op("CP B", 1, 1, "Z1HC", "")),
+    Some(op("CP C", 1, 1, "Z1HC", "")),
+    Some(op("CP D", 1, 1, "Z1HC", "")),
+    Some(op("CP E", 1, 1, "Z1HC", "")),
+    Some(op("CP H", 1, 1, "Z1HC", "")),
+    Some(op("CP L", 1, 1, "Z1HC", "")),
+    Some(op("CP (HL)", 1, 2, "Z1HC", "")),
+    Some(op("CP A", 1, 1, "Z1HC", "")),
+    // 0xC0
+    Some(branch("RET NZ", 1, 2, 5, "Z")),
+    Some(op("POP BC", 1, 3, "----", "")),
+    Some(branch("JP NZ,a16", 3, 3, 4, "Z")),
+    Some(op("JP a16", 3, 4, "----", "")),
+    Some(branch("CALL NZ,a16", 3, 3, 6, "Z")),
+    Some(op("PUSH BC", 1, 4, "----", "")),
+    Some(op("ADD A,d8", 2, 2, "Z0HC", "")),
+    Some(op("RST 00H", 1, 4, "----", "")),
+    Some(branch("RET Z", 1, 2, 5, "Z")),
+    Some(op("RET", 1, 4, "----", "")),
+    Some(branch("JP Z,a16", 3, 3, 4, "Z")),
+    Some(op("PREFIX CB", 1, 1, "----", "")),
+    Some(branch("CALL Z,a16", 3, 3, 6, "Z")),
+    Some(op("CALL a16", 3, 6, "----", "")),
+    Some(op("ADC A,d8", 2, 2, "Z0HC", "C")),
+    Some(op("RST 08H", 1, 4, "----", "")),
+    // 0xD0
+    Some(branch("RET NC", 1, 2, 5, "C")),
+    Some(op("POP DE", 1, 3, "----", "")),
+    Some(branch("JP NC,a16", 3, 3, 4, "C")),
+    None,
+    Some(branch("CALL NC,a16", 3, 3, 6, "C")),
+    Some(op("PUSH DE", 1, 4, "----", "")),
+    Some(op("SUB d8", 2, 2, "Z1HC", "")),
+    Some(op("RST 10H", 1, 4, "----", "")),
+    Some(branch("RET C", 1, 2, 5, "C")),
+    Some(op("RETI", 1, 4, "----", "")),
+    Some(branch("JP C,a16", 3, 3, 4, "C")),
+    None,
+    Some(branch("CALL C,a16", 3, 3, 6, "C")),
+    None,
+    Some(op("SBC A,d8", 2, 2, "Z1HC", "C")),
+    Some(op("RST 18H", 1, 4, "----", "")),
+    // 0xE0
+    Some(op("LDH (a8),A", 2, 3, "----", "")),
+    Some(op("POP HL", 1, 3, "----", "")),
+    Some(op("LD (C),A", 1, 2, "----", "")),
+    None,
+    None,
+    Some(op("PUSH HL", 1, 4, "----", "")),
+    Some(op("AND d8", 2, 2, "Z010", "")),
+    Some(op("RST 20H", 1, 4, "----", "")),
+    Some(op("ADD SP,r8", 2, 4, "00HC", "")),
+    Some(op("JP HL", 1, 1, "----", "")),
+    Some(op("LD (a16),A", 3, 4, "----", "")),
+    None,
+    None,
+    None,
+    Some(op("XOR d8", 2, 2, "Z000", "")),
+    Some(op("RST 28H", 1, 4, "----", "")),
+    // 0xF0
+    Some(op("LDH A,(a8)", 2, 3, "----", "")),
+    Some(op("POP AF", 1, 3, "ZNHC", "")),
+    Some(op("LD A,(C)", 1, 2, "----", "")),
+    Some(op("DI", 1, 1, "----", "")),
+    None,
+    Some(op("PUSH AF", 1, 4, "----", "ZNHC")),
+    Some(op("OR d8", 2, 2, "Z000", "")),
+    Some(op("RST 30H", 1, 4, "----", "")),
+    Some(op("LD HL,SP+r8", 2, 3, "00HC", "")),
+    Some(op("LD SP,HL", 1, 2, "----", "")),
+    Some(op("LD A,(a16)", 3, 4, "----", "")),
+    Some(op("EI", 1, 1, "----", "")),
+    None,
+    None,
+    Some(op("CP d8", 2, 2, "Z1HC", "")),
+    Some(op("RST 38H", 1, 4, "----", "")),
+];
+
+static EXTENDED_OPCODE_INFO: [OpcodeInfo; 0x100] = [
+    // 0xCB00
+    op("RLC B", 2, 2, "Z00C", ""),
+    op("RLC C", 2, 2, "Z00C", ""),
+    op("RLC D", 2, 2, "Z00C", ""),
+    op("RLC E", 2, 2, "Z00C", ""),
+    op("RLC H", 2, 2, "Z00C", ""),
+    op("RLC L", 2, 2, "Z00C", ""),
+    op("RLC (HL)", 2, 4, "Z00C", ""),
+    op("RLC A", 2, 2, "Z00C", ""),
+    op("RRC B", 2, 2, "Z00C", ""),
+    op("RRC C", 2, 2, "Z00C", ""),
+    op("RRC D", 2, 2, "Z00C", ""),
+    op("RRC E", 2, 2, "Z00C", ""),
+    op("RRC H", 2, 2, "Z00C", ""),
+    op("RRC L", 2, 2, "Z00C", ""),
+    op("RRC (HL)", 2, 4, "Z00C", ""),
+    op("RRC A", 2, 2, "Z00C", ""),
+    // 0xCB10
+    op("RL B", 2, 2, "Z00C", "C"),
+    op("RL C", 2, 2, "Z00C", "C"),
+    op("RL D", 2, 2, "Z00C", "C"),
+    op("RL E", 2, 2, "Z00C", "C"),
+    op("RL H", 2, 2, "Z00C", "C"),
+    op("RL L", 2, 2, "Z00C", "C"),
+    op("RL (HL)", 2, 4, "Z00C", "C"),
+    op("RL A", 2, 2, "Z00C", "C"),
+    op("RR B", 2, 2, "Z00C", "C"),
+    op("RR C", 2, 2, "Z00C", "C"),
+    op("RR D", 2, 2, "Z00C", "C"),
+    op("RR E", 2, 2, "Z00C", "C"),
+    op("RR H", 2, 2, "Z00C", "C"),
+    op("RR L", 2, 2, "Z00C", "C"),
+    op("RR (HL)", 2, 4, "Z00C", "C"),
+    op("RR A", 2, 2, "Z00C", "C"),
+    // 0xCB20
+    op("SLA B", 2, 2, "Z00C", ""),
+    op("SLA C", 2, 2, "Z00C", ""),
+    op("SLA D", 2, 2, "Z00C", ""),
+    op("SLA E", 2, 2, "Z00C", ""),
+    op("SLA H", 2, 2, "Z00C", ""),
+    op("SLA L", 2, 2, "Z00C", ""),
+    op("SLA (HL)", 2, 4, "Z00C", ""),
+    op("SLA A", 2, 2, "Z00C", ""),
+    op("SRA B", 2, 2, "Z00C", ""),
+    op("SRA C", 2, 2, "Z00C", ""),
+    op("SRA D", 2, 2, "Z00C", ""),
+    op("SRA E", 2, 2, "Z00C", ""),
+    op("SRA H", 2, 2, "Z00C", ""),
+    op("SRA L", 2, 2, "Z00C", ""),
+    op("SRA (HL)", 2, 4, "Z00C", ""),
+    op("SRA A", 2, 2, "Z00C", ""),
+    // 0xCB30
+    op("SWAP B", 2, 2, "Z000", ""),
+    op("SWAP C", 2, 2, "Z000", ""),
+    op("SWAP D", 2, 2, "Z000", ""),
+    op("SWAP E", 2, 2, "Z000", ""),
+    op("SWAP H", 2, 2, "Z000", ""),
+    op("SWAP L", 2, 2, "Z000", ""),
+    op("SWAP (HL)", 2, 4, "Z000", ""),
+    op("SWAP A", 2, 2, "Z000", ""),
+    op("SRL B", 2, 2, "Z00C", ""),
+    op("SRL C", 2, 2, "Z00C", ""),
+    op("SRL D", 2, 2, "Z00C", ""),
+    op("SRL E", 2, 2, "Z00C", ""),
+    op("SRL H", 2, 2, "Z00C", ""),
+    op("SRL L", 2, 2, "Z00C", ""),
+    op("SRL (HL)", 2, 4, "Z00C", ""),
+    op("SRL A", 2, 2, "Z00C", ""),
+    // 0xCB40
+    op("BIT 0,B", 2, 2, "Z01-", ""),
+    op("BIT 0,C", 2, 2, "Z01-", ""),
+    op("BIT 0,D", 2, 2, "Z01-", ""),
+    op("BIT 0,E", 2, 2, "Z01-", ""),
+    op("BIT 0,H", 2, 2, "Z01-", ""),
+    op("BIT 0,L", 2, 2, "Z01-", ""),
+    op("BIT 0,(HL)", 2, 3, "Z01-", ""),
+    op("BIT 0,A", 2, 2, "Z01-", ""),
+    op("BIT 1,B", 2, 2, "Z01-", ""),
+    op("BIT 1,C", 2, 2, "Z01-", ""),
+    op("BIT 1,D", 2, 2, "Z01-", ""),
+    op("BIT 1,E", 2, 2, "Z01-", ""),
+    op("BIT 1,H", 2, 2, "Z01-", ""),
+    op("BIT 1,L", 2, 2, "Z01-", ""),
+    op("BIT 1,(HL)", 2, 3, "Z01-", ""),
+    op("BIT 1,A", 2, 2, "Z01-", ""),
+    // 0xCB50
+    op("BIT 2,B", 2, 2, "Z01-", ""),
+    op("BIT 2,C", 2, 2, "Z01-", ""),
+    op("BIT 2,D", 2, 2, "Z01-", ""),
+    op("BIT 2,E", 2, 2, "Z01-", ""),
+    op("BIT 2,H", 2, 2, "Z01-", ""),
+    op("BIT 2,L", 2, 2, "Z01-", ""),
+    op("BIT 2,(HL)", 2, 3, "Z01-", ""),
+    op("BIT 2,A", 2, 2, "Z01-", ""),
+    op("BIT 3,B", 2, 2, "Z01-", ""),
+    op("BIT 3,C", 2, 2, "Z01-", ""),
+    op("BIT 3,D", 2, 2, "Z01-", ""),
+    op("BIT 3,E", 2, 2, "Z01-", ""),
+    op("BIT 3,H", 2, 2, "Z01-", ""),
+    op("BIT 3,L", 2, 2, "Z01-", ""),
+    op("BIT 3,(HL)", 2, 3, "Z01-", ""),
+    op("BIT 3,A", 2, 2, "Z01-", ""),
+    // 0xCB60
+    op("BIT 4,B", 2, 2, "Z01-", ""),
+    op("BIT 4,C", 2, 2, "Z01-", ""),
+    op("BIT 4,D", 2, 2, "Z01-", ""),
+    op("BIT 4,E", 2, 2, "Z01-", ""),
+    op("BIT 4,H", 2, 2, "Z01-", ""),
+    op("BIT 4,L", 2, 2, "Z01-", ""),
+    op("BIT 4,(HL)", 2, 3, "Z01-", ""),
+    op("BIT 4,A", 2, 2, "Z01-", ""),
+    op("BIT 5,B", 2, 2, "Z01-", ""),
+    op("BIT 5,C", 2, 2, "Z01-", ""),
+    op("BIT 5,D", 2, 2, "Z01-", ""),
+    op("BIT 5,E", 2, 2, "Z01-", ""),
+    op("BIT 5,H", 2, 2, "Z01-", ""),
+    op("BIT 5,L", 2, 2, "Z01-", ""),
+    op("BIT 5,(HL)", 2, 3, "Z01-", ""),
+    op("BIT 5,A", 2, 2, "Z01-", ""),
+    // 0xCB70
+    op("BIT 6,B", 2, 2, "Z01-", ""),
+    op("BIT 6,C", 2, 2, "Z01-", ""),
+    op("BIT 6,D", 2, 2, "Z01-", ""),
+    op("BIT 6,E", 2, 2, "Z01-", ""),
+    op("BIT 6,H", 2, 2, "Z01-", ""),
+    op("BIT 6,L", 2, 2, "Z01-", ""),
+    op("BIT 6,(HL)", 2, 3, "Z01-", ""),
+    op("BIT 6,A", 2, 2, "Z01-", ""),
+    op("BIT 7,B", 2, 2, "Z01-", ""),
+    op("BIT 7,C", 2, 2, "Z01-", ""),
+    op("BIT 7,D", 2, 2, "Z01-", ""),
+    op("BIT 7,E", 2, 2, "Z01-", ""),
+    op("BIT 7,H", 2, 2, "Z01-", ""),
+    op("BIT 7,L", 2, 2, "Z01-", ""),
+    op("BIT 7,(HL)", 2, 3, "Z01-", ""),
+    op("BIT 7,A", 2, 2, "Z01-", ""),
+    // 0xCB80
+    op("RES 0,B", 2, 2, "----", ""),
+    op("RES 0,C", 2, 2, "----", ""),
+    op("RES 0,D", 2, 2, "----", ""),
+    op("RES 0,E", 2, 2, "----", ""),
+    op("RES 0,H", 2, 2, "----", ""),
+    op("RES 0,L", 2, 2, "----", ""),
+    op("RES 0,(HL)", 2, 4, "----", ""),
+    op("RES 0,A", 2, 2, "----", ""),
+    op("RES 1,B", 2, 2, "----", ""),
+    op("RES 1,C", 2, 2, "----", ""),
+    op("RES 1,D", 2, 2, "----", ""),
+    op("RES 1,E", 2, 2, "----", ""),
+    op("RES 1,H", 2, 2, "----", ""),
+    op("RES 1,L", 2, 2, "----", ""),
+    op("RES 1,(HL)", 2, 4, "----", ""),
+    op("RES 1,A", 2, 2, "----", ""),
+    // 0xCB90
+    op("RES 2,B", 2, 2, "----", ""),
+    op("RES 2,C", 2, 2, "----", ""),
+    op("RES 2,D", 2, 2, "----", ""),
+    op("RES 2,E", 2, 2, "----", ""),
+    op("RES 2,H", 2, 2, "----", ""),
+    op("RES 2,L", 2, 2, "----", ""),
+    op("RES 2,(HL)", 2, 4, "----", ""),
+    op("RES 2,A", 2, 2, "----", ""),
+    op("RES 3,B", 2, 2, "----", ""),
+    op("RES 3,C", 2, 2, "----", ""),
+    op("RES 3,D", 2, 2, "----", ""),
+    op("RES 3,E", 2, 2, "----", ""),
+    op("RES 3,H", 2, 2, "----", ""),
+    op("RES 3,L", 2, 2, "----", ""),
+    op("RES 3,(HL)", 2, 4, "----", ""),
+    op("RES 3,A", 2, 2, "----", ""),
+    // 0xCBA0
+    op("RES 4,B", 2, 2, "----", ""),
+    op("RES 4,C", 2, 2, "----", ""),
+    op("RES 4,D", 2, 2, "----", ""),
+    op("RES 4,E", 2, 2, "----", ""),
+    op("RES 4,H", 2, 2, "----", ""),
+    op("RES 4,L", 2, 2, "----", ""),
+    op("RES 4,(HL)", 2, 4, "----", ""),
+    op("RES 4,A", 2, 2, "----", ""),
+    op("RES 5,B", 2, 2, "----", ""),
+    op("RES 5,C", 2, 2, "----", ""),
+    op("RES 5,D", 2, 2, "----", ""),
+    op("RES 5,E", 2, 2, "----", ""),
+    op("RES 5,H", 2, 2, "----", ""),
+    op("RES 5,L", 2, 2, "----", ""),
+    op("RES 5,(HL)", 2, 4, "----", ""),
+    op("RES 5,A", 2, 2, "----", ""),
+    // 0xCBB0
+    op("RES 6,B", 2, 2, "----", ""),
+    op("RES 6,C", 2, 2, "----", ""),
+    op("RES 6,D", 2, 2, "----", ""),
+    op("RES 6,E", 2, 2, "----", ""),
+    op("RES 6,H", 2, 2, "----", ""),
+    op("RES 6,L", 2, 2, "----", ""),
+    op("RES 6,(HL)", 2, 4, "----", ""),
+    op("RES 6,A", 2, 2, "----", ""),
+    op("RES 7,B", 2, 2, "----", ""),
+    op("RES 7,C", 2, 2, "----", ""),
+    op("RES 7,D", 2, 2, "----", ""),
+    op("RES 7,E", 2, 2, "----", ""),
+    op("RES 7,H", 2, 2, "----", ""),
+    op("RES 7,L", 2, 2, "----", ""),
+    op("RES 7,(HL)", 2, 4, "----", ""),
+    op("RES 7,A", 2, 2, "----", ""),
+    // 0xCBC0
+    op("SET 0,B", 2, 2, "----", ""),
+    op("SET 0,C", 2, 2, "----", ""),
+    op("SET 0,D", 2, 2, "----", ""),
+    op("SET 0,E", 2, 2, "----", ""),
+    op("SET 0,H", 2, 2, "----", ""),
+    op("SET 0,L", 2, 2, "----", ""),
+    op("SET 0,(HL)", 2, 4, "----", ""),
+    op("SET 0,A", 2, 2, "----", ""),
+    op("SET 1,B", 2, 2, "----", ""),
+    op("SET 1,C", 2, 2, "----", ""),
+    op("SET 1,D", 2, 2, "----", ""),
+    op("SET 1,E", 2, 2, "----", ""),
+    op("SET 1,H", 2, 2, "----", ""),
+    op("SET 1,L", 2, 2, "----", ""),
+    op("SET 1,(HL)", 2, 4, "----", ""),
+    op("SET 1,A", 2, 2, "----", ""),
+    // 0xCBD0
+    op("SET 2,B", 2, 2, "----", ""),
+    op("SET 2,C", 2, 2, "----", ""),
+    op("SET 2,D", 2, 2, "----", ""),
+    op("SET 2,E", 2, 2, "----", ""),
+    op("SET 2,H", 2, 2, "----", ""),
+    op("SET 2,L", 2, 2, "----", ""),
+    op("SET 2,(HL)", 2, 4, "----", ""),
+    op("SET 2,A", 2, 2, "----", ""),
+    op("SET 3,B", 2, 2, "----", ""),
+    op("SET 3,C", 2, 2, "----", ""),
+    op("SET 3,D", 2, 2, "----", ""),
+    op("SET 3,E", 2, 2, "----", ""),
+    op("SET 3,H", 2, 2, "----", ""),
+    op("SET 3,L", 2, 2, "----", ""),
+    op("SET 3,(HL)", 2, 4, "----", ""),
+    op("SET 3,A", 2, 2, "----", ""),
+    // 0xCBE0
+    op("SET 4,B", 2, 2, "----", ""),
+    op("SET 4,C", 2, 2, "----", ""),
+    op("SET 4,D", 2, 2, "----", ""),
+    op("SET 4,E", 2, 2, "----", ""),
+    op("SET 4,H", 2, 2, "----", ""),
+    op("SET 4,L", 2, 2, "----", ""),
+    op("SET 4,(HL)", 2, 4, "----", ""),
+    op("SET 4,A", 2, 2, "----", ""),
+    op("SET 5,B", 2, 2, "----", ""),
+    op("SET 5,C", 2, 2, "----", ""),
+    op("SET 5,D", 2, 2, "----", ""),
+    op("SET 5,E", 2, 2, "----", ""),
+    op("SET 5,H", 2, 2, "----", ""),
+    op("SET 5,L", 2, 2, "----", ""),
+    op("SET 5,(HL)", 2, 4, "----", ""),
+    op("SET 5,A", 2, 2, "----", ""),
+    // 0xCBF0
+    op("SET 6,B", 2, 2, "----", ""),
+    op("SET 6,C", 2, 2, "----", ""),
+    op("SET 6,D", 2, 2, "----", ""),
+    op("SET 6,E", 2, 2, "----", ""),
+    op("SET 6,H", 2, 2, "----", ""),
+    op("SET 6,L", 2, 2, "----", ""),
+    op("SET 6,(HL)", 2, 4, "----", ""),
+    op("SET 6,A", 2, 2, "----", ""),
+    op("SET 7,B", 2, 2, "----", ""),
+    op("SET 7,C", 2, 2, "----", ""),
+    op("SET 7,D", 2, 2, "----", ""),
+    op("SET 7,E", 2, 2, "----", ""),
+    op("SET 7,H", 2, 2, "----", ""),
+    op("SET 7,L", 2, 2, "----", ""),
+    op("SET 7,(HL)", 2, 4, "----", ""),
+    op("SET 7,A", 2, 2, "----", ""),
+];
@@ -0,0 +1,344 @@
+use crate::interupt::Interrupt;
+
+const DOTS_PER_LINE: u32 = 456;
+const OAM_SCAN_DOTS: u32 = 80;
+const PIXEL_TRANSFER_DOTS: u32 = 172;
+const VISIBLE_LINES: u8 = 144;
+const TOTAL_LINES: u8 = 154;
+const VRAM_SIZE: usize = 0x2000;
+const OAM_SIZE: usize = 0xA0;
+const SPRITES_PER_LINE: usize = 10;
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PPUMode {
+    HBlank = 0,
+    VBlank = 1,
+    OAMScan = 2,
+    PixelTransfer = 3,
+}
+
+struct Sprite {
+    y: i16,
+    x: i16,
+    tile: u8,
+    attributes: u8,
+}
+
+impl Sprite {
+    fn from_oam(entry: &[u8]) -> Sprite {
+        // OAM stores the coordinates offset so sprites can be partially off-screen
+        Sprite {
+            y: entry[0] as i16 - 16,
+            x: entry[1] as i16 - 8,
+            tile: entry[2],
+            attributes: entry[3],
+        }
+    }
+
+    fn is_behind_background(&self) -> bool {
+        self.attributes & 0b1000_0000 != 0
+    }
+
+    fn is_y_flipped(&self) -> bool {
+        self.attributes & 0b0100_0000 != 0
+    }
+
+    fn is_x_flipped(&self) -> bool {
+        self.attributes & 0b0010_0000 != 0
+    }
+
+    fn uses_second_palette(&self) -> bool {
+        self.attributes & 0b0001_0000 != 0
+    }
+}
+
+pub struct PPU {
+    vram: [u8; VRAM_SIZE],
+    oam: [u8; OAM_SIZE],
+    framebuffer: [u8; SCREEN_WIDTH * SCREEN_HEIGHT],
+    lcd_control: u8, // lcdc
+    lcd_status: u8, // stat, only bits 3-6 are stored here
+    scroll_y: u8, // scy
+    scroll_x: u8, // scx
+    line: u8, // ly
+    line_compare: u8, // lyc
+    bg_palette: u8, // bgp
+    obj_palettes: [u8; 2], // obp0, obp1
+    window_y: u8, // wy
+    window_x: u8, // wx
+    // the window keeps its own line counter, which only advances on lines where it was drawn
+    window_line: u8,
+    mode: PPUMode,
+    dot: u32,
+    // STAT interrupts only fire on a rising edge of the OR of all enabled sources
+    stat_line: bool,
+}
+
+impl PPU {
+    pub fn new() -> PPU {
+        PPU {
+            vram: [0; VRAM_SIZE],
+            oam: [0; OAM_SIZE],
+            framebuffer: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            lcd_control: 0x91,
+            lcd_status: 0,
+            scroll_y: 0,
+            scroll_x: 0,
+            line: 0,
+            line_compare: 0,
+            bg_palette: 0xFC,
+            obj_palettes: [0xFF, 0xFF],
+            window_y: 0,
+            window_x: 0,
+            window_line: 0,
+            mode: PPUMode::OAMScan,
+            dot: 0,
+            stat_line: false,
+        }
+    }
+
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0x8000 ..= 0x9FFF => self.vram[address as usize - 0x8000],
+            0xFE00 ..= 0xFE9F => self.oam[address as usize - 0xFE00],
+            0xFF40 => self.lcd_control,
+            0xFF41 => {
+                let coincidence = if self.line == self.line_compare { 0b100 } else { 0 };
+                0x80 | self.lcd_status | coincidence | self.mode as u8
+            }
+            0xFF42 => self.scroll_y,
+            0xFF43 => self.scroll_x,
+            0xFF44 => self.line,
+            0xFF45 => self.line_compare,
+            0xFF47 => self.bg_palette,
+            0xFF48 => self.obj_palettes[0],
+            0xFF49 => self.obj_palettes[1],
+            0xFF4A => self.window_y,
+            0xFF4B => self.window_x,
+            _ => unreachable!("Invalid address accessed in ppu: {}", address)
+        }
+    }
+
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0x8000 ..= 0x9FFF => self.vram[address as usize - 0x8000] = value,
+            0xFE00 ..= 0xFE9F => self.oam[address as usize - 0xFE00] = value,
+            0xFF40 => {
+                let was_enabled = self.is_enabled();
+                self.lcd_control = value;
+                if was_enabled && !self.is_enabled() {
+                    // turning the LCD off resets the PPU to the start of the frame
+                    self.line = 0;
+                    self.dot = 0;
+                    self.mode = PPUMode::HBlank;
+                    self.stat_line = false;
+                    self.window_line = 0;
+                } else if !was_enabled && self.is_enabled() {
+                    self.mode = PPUMode::OAMScan;
+                }
+            }
+            0xFF41 => self.lcd_status = value & 0b0111_1000,
+            0xFF42 => self.scroll_y = value,
+            0xFF43 => self.scroll_x = value,
+            // LY is read only
+            0xFF44 => {}
+            0xFF45 => self.line_compare = value,
+            0xFF47 => self.bg_palette = value,
+            0xFF48 => self.obj_palettes[0] = value,
+            0xFF49 => self.obj_palettes[1] = value,
+            0xFF4A => self.window_y = value,
+            0xFF4B => self.window_x = value,
+            _ => unreachable!("Invalid address accessed in ppu: {}", address)
+        }
+    }
+
+    /// Advances the PPU by `elapsed` machine cycles, returning the interrupt flags
+    /// that should be raised, in the same layout as the IF register.
+    pub fn tick(&mut self, elapsed: u32) -> u8 {
+        let mut interrupts = 0;
+        if !self.is_enabled() {
+            return interrupts;
+        }
+
+        // a machine cycle is four dots
+        for _ in 0..elapsed * 4 {
+            self.dot += 1;
+            if self.dot == DOTS_PER_LINE {
+                self.dot = 0;
+                self.line = (self.line + 1) % TOTAL_LINES;
+                if self.line == 0 {
+                    self.window_line = 0;
+                }
+            }
+
+            let mode = self.mode_for_current_dot();
+            if mode != self.mode {
+                self.mode = mode;
+                match mode {
+                    PPUMode::VBlank => interrupts |= 1 << Interrupt::VBlank.get_index(),
+                    // the whole line is drawn at once when pixel transfer finishes
+                    PPUMode::HBlank => self.render_scanline(),
+                    _ => {}
+                }
+            }
+
+            if self.update_stat_line() {
+                interrupts |= 1 << Interrupt::LCDStatus.get_index();
+            }
+        }
+        interrupts
+    }
+
+    /// Shades for every pixel of the last completed frame, from 0 (lightest) to 3 (darkest),
+    /// laid out row by row.
+    pub fn framebuffer(&self) -> &[u8; SCREEN_WIDTH * SCREEN_HEIGHT] {
+        &self.framebuffer
+    }
+
+    fn render_scanline(&mut self) {
+        let line = self.line as usize;
+        let window_visible = self.lcd_control & 0b0010_0000 != 0
+            && self.line >= self.window_y
+            && self.window_x <= 166;
+        // on the DMG, clearing LCDC bit 0 blanks both the background and the window
+        let bg_enabled = self.lcd_control & 0b1 != 0;
+
+        // raw color indexes are kept around since sprite priority depends on them
+        let mut bg_colors = [0; SCREEN_WIDTH];
+        for (x, bg_color) in bg_colors.iter_mut().enumerate() {
+            *bg_color = if !bg_enabled {
+                0
+            } else if window_visible && x + 7 >= self.window_x as usize {
+                let map = if self.lcd_control & 0b0100_0000 != 0 { 0x9C00 } else { 0x9800 };
+                let window_x = (x + 7 - self.window_x as usize) as u8;
+                self.tile_color(map, window_x, self.window_line)
+            } else {
+                let map = if self.lcd_control & 0b0000_1000 != 0 { 0x9C00 } else { 0x9800 };
+                let bg_x = self.scroll_x.wrapping_add(x as u8);
+                let bg_y = self.scroll_y.wrapping_add(self.line);
+                self.tile_color(map, bg_x, bg_y)
+            };
+        }
+
+        if bg_enabled && window_visible {
+            self.window_line += 1;
+        }
+
+        let sprites = if self.lcd_control & 0b10 != 0 { self.sprites_on_line() } else { Vec::new() };
+        for (x, bg_color) in bg_colors.iter().enumerate() {
+            let mut shade = Self::apply_palette(self.bg_palette, *bg_color);
+            // the first opaque sprite pixel wins, even when it ends up hidden behind the background
+            let sprite_pixel = sprites.iter()
+                .map(|sprite| (sprite, self.sprite_color(sprite, x as i16)))
+                .find(|(_, color)| *color != 0);
+            if let Some((sprite, color)) = sprite_pixel {
+                if !sprite.is_behind_background() || *bg_color == 0 {
+                    let palette = self.obj_palettes[sprite.uses_second_palette() as usize];
+                    shade = Self::apply_palette(palette, color);
+                }
+            }
+            self.framebuffer[line * SCREEN_WIDTH + x] = shade;
+        }
+    }
+
+    /// Selects the sprites drawn on the current line, ordered from highest to lowest priority.
+    fn sprites_on_line(&self) -> Vec<Sprite> {
+        let height = self.sprite_height();
+        let line = self.line as i16;
+        // only the first ten sprites in OAM order that overlap the line are drawn
+        let mut sprites: Vec<Sprite> = self.oam.chunks(4)
+            .map(Sprite::from_oam)
+            .filter(|sprite| line >= sprite.y && line < sprite.y + height)
+            .take(SPRITES_PER_LINE)
+            .collect();
+        // on the DMG, the sprite with the smaller X wins, with ties broken by OAM order.
+        // The sort is stable, so OAM order is kept for sprites with the same X.
+        sprites.sort_by_key(|sprite| sprite.x);
+        sprites
+    }
+
+    fn sprite_color(&self, sprite: &Sprite, x: i16) -> u8 {
+        if x < sprite.x || x >= sprite.x + 8 {
+            return 0;
+        }
+        let height = self.sprite_height();
+        let mut row = self.line as i16 - sprite.y;
+        if sprite.is_y_flipped() {
+            row = height - 1 - row;
+        }
+        let mut column = (x - sprite.x) as u8;
+        if sprite.is_x_flipped() {
+            column = 7 - column;
+        }
+        // 8x16 sprites ignore the lowest bit of the tile index
+        let tile = if height == 16 { sprite.tile & 0xFE } else { sprite.tile };
+        let address = 0x8000 + tile as u16 * 16 + row as u16 * 2;
+        Self::pixel_color(self.read_vram(address), self.read_vram(address + 1), column)
+    }
+
+    fn sprite_height(&self) -> i16 {
+        if self.lcd_control & 0b100 != 0 { 16 } else { 8 }
+    }
+
+    /// Looks up the 2bpp color index of the pixel at (x, y) in the 256x256 map at `map`.
+    fn tile_color(&self, map: u16, x: u8, y: u8) -> u8 {
+        let tile_index = (y as u16 / 8) * 32 + x as u16 / 8;
+        let tile = self.read_vram(map + tile_index);
+        let tile_address = if self.lcd_control & 0b0001_0000 != 0 {
+            0x8000 + tile as u16 * 16
+        } else {
+            // 0x8800 addressing uses signed tile indexes relative to 0x9000
+            (0x9000 + (tile as i8 as i32) * 16) as u16
+        };
+        let row = tile_address + (y as u16 % 8) * 2;
+        Self::pixel_color(self.read_vram(row), self.read_vram(row + 1), x % 8)
+    }
+
+    /// Decodes pixel `x` (0 is leftmost) of a tile row stored as two bit planes.
+    fn pixel_color(low: u8, high: u8, x: u8) -> u8 {
+        let bit = 7 - x;
+        ((high >> bit) & 1) << 1 | ((low >> bit) & 1)
+    }
+
+    fn apply_palette(palette: u8, color: u8) -> u8 {
+        (palette >> (color * 2)) & 0b11
+    }
+
+    fn read_vram(&self, address: u16) -> u8 {
+        self.vram[address as usize - 0x8000]
+    }
+
+    fn mode_for_current_dot(&self) -> PPUMode {
+        if self.line >= VISIBLE_LINES {
+            PPUMode::VBlank
+        } else if self.dot < OAM_SCAN_DOTS {
+            PPUMode::OAMScan
+        } else if self.dot < OAM_SCAN_DOTS + PIXEL_TRANSFER_DOTS {
+            PPUMode::PixelTransfer
+        } else {
+            PPUMode::HBlank
+        }
+    }
+
+    /// Recomputes the STAT interrupt line and returns true on a rising edge.
+    fn update_stat_line(&mut self) -> bool {
+        let line = (self.lcd_status & 0b0100_0000 != 0 && self.line == self.line_compare)
+            || match self.mode {
+                PPUMode::HBlank => self.lcd_status & 0b0000_1000 != 0,
+                // the OAM interrupt source also fires at the start of VBlank
+                PPUMode::VBlank => self.lcd_status & 0b0011_0000 != 0,
+                PPUMode::OAMScan => self.lcd_status & 0b0010_0000 != 0,
+                PPUMode::PixelTransfer => false,
+            };
+        let rising = line && !self.stat_line;
+        self.stat_line = line;
+        rising
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.lcd_control & 0x80 != 0
+    }
+}
@@ -23,6 +23,22 @@ pub struct Registers {
 }
 
 impl Registers {
+    /// The state at power on, before a boot rom has run.
+    pub fn zeroed() -> Registers {
+        Self {
+            a: 0,
+            f: 0,
+            b: 0,
+            c: 0,
+            d: 0,
+            e: 0,
+            h: 0,
+            l: 0,
+            pc: 0,
+            sp: 0,
+        }
+    }
+
     pub fn new() -> Registers {
         Self {
             a: 1,
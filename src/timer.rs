@@ -1,84 +1,92 @@
-
 pub struct Timer {
-    divider_register: u8, // div
+    // div is the upper byte of this counter, which advances every clock
+    system_counter: u16,
     timer_counter: u8, // tima
     timer_modulo: u8, // tma
     timer_control: u8, // tac
-    internal_counter: u32,
-    divider_counter: u32,
 }
 
 impl Timer {
     pub fn new() -> Timer {
         Timer {
-            divider_register: 0,
+            system_counter: 0xABCC,
             timer_counter: 0,
             timer_modulo: 0,
             timer_control: 0,
-            internal_counter: 0,
-            divider_counter: 0,
         }
     }
 
     pub fn read_byte(&self, address: u16) -> u8 {
         match address {
-            0xFF04 => self.divider_register,
+            0xFF04 => (self.system_counter >> 8) as u8,
             0xFF05 => self.timer_counter,
             0xFF06 => self.timer_modulo,
-            0xFF07 => self.timer_control,
+            0xFF07 => 0xF8 | self.timer_control,
             _ => unreachable!("Invalid address accessed in timer: {}", address)
         }
     }
 
-    pub fn write_byte(&mut self, address: u16, value: u8) {
+    /// Returns true if the write caused tima to overflow.
+    pub fn write_byte(&mut self, address: u16, value: u8) -> bool {
+        // resetting div or changing tac can produce a falling edge on the timer input
+        let previous = self.timer_input();
         match address {
             0xFF04 =>
-                self.divider_register = 0,
+                self.system_counter = 0,
             0xFF05 =>
                 self.timer_counter = value,
             0xFF06 =>
                 self.timer_modulo = value,
             0xFF07 =>
-                self.timer_control = value,
+                self.timer_control = value & 0b111,
             _ => unreachable!("Invalid address accessed in timer: {}", address)
         }
+        previous && !self.timer_input() && self.increment_timer_counter()
     }
 
-    pub fn tick(&mut self, elapsed: u32) -> bool {
-        self.divider_counter += elapsed;
-        self.divider_register = self.divider_register.wrapping_add((self.divider_counter / 256) as u8);
-        self.divider_counter %= 256;
-
-        if self.is_enabled() {
-            self.internal_counter += elapsed;
-            let (updated, overflow) = self.timer_counter.overflowing_add((self.internal_counter / self.get_speed()) as u8);
-            self.internal_counter %= self.get_speed();
-
-            self.timer_counter = if overflow {
-                self.timer_modulo + self.internal_counter as u8
-            } else {
-                updated
-            };
+    /// The full internal divider, of which div exposes the upper byte. Other units, such as
+    /// the APU frame sequencer, are clocked from its bits.
+    pub fn divider(&self) -> u16 {
+        self.system_counter
+    }
 
-            if overflow {
-                return true
+    pub fn tick(&mut self, elapsed: u32) -> bool {
+        let mut overflow = false;
+        for _ in 0..elapsed {
+            let previous = self.timer_input();
+            // a machine cycle is four clocks
+            self.system_counter = self.system_counter.wrapping_add(4);
+            if previous && !self.timer_input() {
+                overflow |= self.increment_timer_counter();
             }
         }
-        false
+        overflow
+    }
+
+    fn increment_timer_counter(&mut self) -> bool {
+        let (updated, overflow) = self.timer_counter.overflowing_add(1);
+        self.timer_counter = if overflow { self.timer_modulo } else { updated };
+        overflow
+    }
+
+    /// tima is incremented on the falling edge of the selected divider bit, gated by the
+    /// enable bit.
+    fn timer_input(&self) -> bool {
+        self.is_enabled() && self.system_counter & self.get_bit_mask() != 0
     }
 
     fn is_enabled(&self) -> bool {
         self.timer_control & 0b100 != 0
     }
 
-    fn get_speed(&self) -> u32 {
+    fn get_bit_mask(&self) -> u16 {
         match self.timer_control & 0b11 {
-            0b00 => 256,
-            0b01 => 4,
-            0b10 => 16,
-            0b11 => 64,
+            0b00 => 1 << 9,
+            0b01 => 1 << 3,
+            0b10 => 1 << 5,
+            0b11 => 1 << 7,
             _ => unreachable!()
         }
     }
 
-}
\ No newline at end of file
+}